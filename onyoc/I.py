@@ -1,5 +1,6 @@
 import json
-from typing import IO, cast
+from itertools import chain
+from typing import IO, Iterable, cast
 
 from lark.lexer import Token
 from lark.visitors import Interpreter
@@ -17,40 +18,148 @@ class I(Interpreter[Token, None], ErrorStorage):
 
    def func(self, node: Tree, qualprefix: str | None = None) -> int:
       name = cast(Token, node.children[0])
-      parameters = optional_list(node.children[1:-1])
+      parameters = cast(list[Token | Tree], optional_list(node.children[1:-1]))
       body = cast(Tree, node.children[-1])
       qualname = qualprefix + str(name) if qualprefix else str(name)
-      qualparameters = [str(i) for i in parameters]
+      function = Function(qualname, [], [], [])
+      has_default = False
+      for i, parameter in enumerate(parameters):
+         if isinstance(parameter, Token):
+            function.parameters.append(str(parameter))
+            if has_default:
+               self.add_error(
+                  f"Parameter `{parameter}` without a default value follows a parameter with one",
+                  range=Range.from_token(parameter),
+               )
+            continue
+         parameter_name = cast(Token, parameter.children[0])
+         function.parameters.append(str(parameter_name))
+         if parameter.data == "param_default":
+            has_default = True
+            self.parameter_trees.append((function, i, cast(Tree, parameter.children[1])))
+         elif i < len(parameters) - 1:
+            self.add_error(
+               f"Rest parameter `{parameter_name}` must be the last parameter", range=Range.from_token(parameter_name)
+            )
+         else:
+            function.rest = True
       if redeclaration := self.functions.get(qualname):
          self.add_error(f"Redeclration of function {redeclaration}")
-      self.functions[qualname] = (len(self.functions), Function(qualname, qualparameters, [], []), body)
+      self.functions[qualname] = (len(self.functions), function, body)
       return self.functions[qualname][0]
 
    def structdef(self, node: Tree):
       name = cast(Token, node.children[0])
       qualname = str(name)
-      it = iter(node.children[1:])
+      if (parent := node.children[1]) is not None:
+         self.parents[qualname] = cast(Token, parent)
+      it = cast(Iterable[Tree], node.children[2:])
       field_map = {}
       method_map = {}
+      defaults: list[tuple[int, Tree]] = []
+      constants: list[Tree] = []
       for current in it:
-         if isinstance(current, lark.Tree) and current.data == "func":
-            method_name = str(current.children[0])
-            id = self.ident_map.get(method_name)
-            if id is None:
-               id = len(self.ident_map)
-               self.ident_map[method_name] = id
-            method_map[id] = self.func(current, qualname + ".")
+         if current is None:  # type: ignore
+            continue
+         if current.data == "func":
+            method_map[self.ident(str(current.children[0]))] = self.func(current, qualname + ".")
+         elif current.data == "constdef":
+            constants.append(current)
          else:
-            field_name = current
-            if field_name is None:  # type: ignore
-               continue
-            field_qualname = str(field_name)
-            id = self.ident_map.get(field_qualname)
-            if id is None:
-               id = len(self.ident_map)
-               self.ident_map[field_qualname] = id
-            field_map[id] = len(field_map)
-      self.structs[name] = len(self.structs), Prototype(qualname, field_map, method_map)
+            field_id = self.ident(str(current.children[0]))
+            field_map[field_id] = len(field_map)
+            if current.children[1] is not None:
+               defaults.append((field_id, current.children[1]))
+      prototype = Prototype(qualname, field_map, method_map)
+      self.structs[name] = len(self.structs), prototype
+      self.default_trees.extend((prototype, field_id, tree) for field_id, tree in defaults)
+      self.constdefs(prototype, constants)
+
+   def enumdef(self, node: Tree):
+      name = cast(Token, node.children[0])
+      qualname = str(name)
+      method_map = {}
+      variants: list[Variant] = []
+      constants: list[Tree] = []
+      for current in cast(list[Tree], node.children[1:]):
+         if current.data == "func":
+            method_map[self.ident(str(current.children[0]))] = self.func(current, qualname + ".")
+            continue
+         if current.data == "constdef":
+            constants.append(current)
+            continue
+         variant_name = cast(Token, current.children[0])
+         if any(variant.name == variant_name for variant in variants):
+            self.add_error(f"Redeclaration of variant `{variant_name}`", range=Range.from_token(variant_name))
+         field_map = {}
+         for field_name in current.children[1:]:
+            if field_name is not None:
+               field_map[self.ident(str(field_name))] = len(field_map)
+         variants.append(Variant(str(variant_name), field_map))
+      prototype = Prototype(qualname, {}, method_map, variants)
+      self.structs[name] = len(self.structs), prototype
+      self.constdefs(prototype, constants)
+
+   def constdefs(self, prototype: Prototype, nodes: list[Tree]):
+      """Adds the constants of a struct or enum, they are compiled after all structs are known."""
+      declared = set(chain(prototype.field_map, prototype.method_map, *(variant.field_map for variant in prototype.variants)))
+      for node in nodes:
+         name = cast(Token, node.children[0])
+         constant_id = self.ident(str(name))
+         if constant_id in declared:
+            self.add_error(f"Redeclaration of `{prototype.name}.{name}`", range=Range.from_token(name))
+            continue
+         declared.add(constant_id)
+         self.constant_trees.append((prototype, constant_id, cast(Tree, node.children[1])))
+
+   def inherit(self, name: str, children: set[str]):
+      """Sets the parent of the struct and copies the fields of the parent before its own fields."""
+      parent_name = self.parents.pop(name, None)
+      if parent_name is None:
+         return
+      children.add(name)
+      parent = self.structs.get(str(parent_name))
+      if parent is None:
+         self.add_error(
+            f"Undefined struct `{parent_name}`",
+            range=Range.from_token(parent_name),
+            typo=typo(str(parent_name), self.structs.keys()),
+         )
+         return
+      if str(parent_name) in children:
+         self.add_error(f"Struct `{name}` inherits from itself", range=Range.from_token(parent_name))
+         return
+      self.inherit(str(parent_name), children)
+      parent_id, parent_prototype = parent
+      if parent_prototype.variants:
+         self.add_error(f"Struct `{name}` cannot inherit from the enum `{parent_name}`", range=Range.from_token(parent_name))
+         return
+      _, prototype = self.structs[name]
+      prototype.parent = parent_id
+      field_map = dict(parent_prototype.field_map)
+      for field_id in prototype.field_map:
+         if field_id not in field_map:
+            field_map[field_id] = len(field_map)
+      prototype.field_map = field_map
+
+   def compile_expr(self, name: str, tree: Tree, description: str) -> ExprT:
+      """Compiles an expression which is evaluated outside of any function, such as a default value."""
+      # Not a method, so that super cannot be used.
+      function = Function(name, [], [], [])
+      v = V(self, function)
+      expr = v.transform(D(self, function).transform(tree))
+      self.errors_from(v)
+      if v.variables:
+         self.add_error(f"{description} cannot assign to variables")
+      return expr
+
+   def ident(self, name: str) -> int:
+      """Returns the id of a field or method name, adding it to the ident map if it is new."""
+      id = self.ident_map.get(name)
+      if id is None:
+         id = len(self.ident_map)
+         self.ident_map[name] = id
+      return id
 
    def package(self, file: str, output_file: IO[str]):
       reserved = ReservedIdents.from_ident_map(self.ident_map)
@@ -68,7 +177,44 @@ class I(Interpreter[Token, None], ErrorStorage):
       self.ident_map: dict[str, int] = {}
       self.functions: dict[str, tuple[int, Function, Tree]] = {}
       self.structs: dict[str, tuple[int, Prototype]] = {}
+      self.parents: dict[str, Token] = {}
+      self.default_trees: list[tuple[Prototype, int, Tree]] = []
+      self.constant_trees: list[tuple[Prototype, int, Tree]] = []
+      self.parameter_trees: list[tuple[Function, int, Tree]] = []
       self.visit_children(root)
+      for name in list(self.parents):
+         self.inherit(name, set())
+      ident_names = {id: name for name, id in self.ident_map.items()}
+      prototypes = [prototype for _, prototype in self.structs.values()]
+      # struct_values looks at the keys of defaults to find missing fields, so
+      # every default, inherited or not, is marked before compiling any
+      # expression. The compiled values are filled in afterwards.
+      inherited: list[tuple[Prototype, int, Prototype]] = []
+      for prototype in prototypes:
+         field_ids = {field_id for owner, field_id, _ in self.default_trees if owner is prototype}
+         parent = prototype.parent
+         while parent is not None:
+            for owner, field_id, _ in self.default_trees:
+               if owner is prototypes[parent] and field_id not in field_ids:
+                  field_ids.add(field_id)
+                  inherited.append((prototype, field_id, owner))
+            parent = prototypes[parent].parent
+         prototype.defaults.update((field_id, Expr.Literal(Literal.Nil())) for field_id in field_ids)
+      for prototype, field_id, tree in self.default_trees:
+         qualname = f"{prototype.name}.{ident_names[field_id]}"
+         prototype.defaults[field_id] = self.compile_expr(qualname.replace(".", "::"), tree, f"Default value of `{qualname}`")
+      for prototype, constant_id, tree in self.constant_trees:
+         qualname = f"{prototype.name}.{ident_names[constant_id]}"
+         prototype.constants[constant_id] = self.compile_expr(qualname.replace(".", "::"), tree, f"Constant `{qualname}`")
+      for function, index, tree in self.parameter_trees:
+         parameter = function.parameters[index]
+         function.defaults[index] = self.compile_expr(
+            f"{function.name.replace('.', '::')}::{parameter}",
+            tree,
+            f"Default value of parameter `{parameter}` of `{function.name}`",
+         )
+      for prototype, field_id, owner in inherited:
+         prototype.defaults[field_id] = owner.defaults[field_id]
       if "main" not in self.functions:
          self.add_error("No main function", suggestion=("Consider adding a main function", Range(0, 0, 0), "main() {}"))
       for _, function, body in self.functions.values():
@@ -76,6 +222,11 @@ class I(Interpreter[Token, None], ErrorStorage):
          v = V(self, function)
          for parameter in function.parameters:
             v.variables[parameter] = len(v.variables)
-         function.body = v.transform(d.transform(body))
+         body = d.transform(body)
+         # The block of a for loop is transformed before the loop itself, so
+         # the loop variables are declared up front.
+         for loop in body.find_data("forloop"):
+            v.variables.setdefault(str(loop.children[0]), len(v.variables))
+         function.body = v.transform(body)
          self.errors_from(v)
          function.variables = list(v.variables.keys())
@@ -1,5 +1,7 @@
+import re
+from dataclasses import dataclass
 from itertools import chain
-from typing import TYPE_CHECKING
+from typing import TYPE_CHECKING, Callable
 
 from lark.lexer import Token
 from lark.visitors import Transformer
@@ -33,10 +35,69 @@ def unary_operation(operator: UnaryOperator):
    return f
 
 
+def nary_operation(operator: NaryOperator):
+   def f(self: "V", args: list[ExprT]):
+      return Expr.NaryOperation(operator, args)
+
+   return f
+
+
+def overload(generators: dict[int, Callable[["V", Any], ExprT]]):
+   """Pick the generator by the number of arguments."""
+
+   def f(self: "V", args: list[ExprT]):
+      return generators[len(args)](self, args)
+
+   return f
+
+
+def plugin(name: str):
+   def f(self: "V", args: list[ExprT]):
+      return Expr.Plugin(PLUGINS[name].id, args)
+
+   return f
+
+
 def flatten(self: "V", args: tuple[T]) -> T:
    return args[0]
 
 
+@dataclass
+class Rest:
+   """Marks the rest-capture of a sequence target or pattern until it is split."""
+
+   value: Any
+   name: Token
+
+
+@dataclass
+class Keyword:
+   """Marks a keyword argument of a call until the call is built."""
+
+   name: Token
+   value: ExprT
+
+
+ESCAPES = {"n": "\n", "r": "\r", "t": "\t", "0": "\0"}
+
+TYPE_NAMES = {
+   "nil",
+   "iterend",
+   "err",
+   "bool",
+   "int",
+   "float",
+   "str",
+   "list",
+   "tuple",
+   "function",
+   "iterator",
+   "file",
+   "range",
+   "prototype",
+}
+
+
 class D(Transformer[Token, Any], ErrorStorage):
    def __init__(self, i: "I", function: Function):
       ErrorStorage.__init__(self)
@@ -51,7 +112,16 @@ class D(Transformer[Token, Any], ErrorStorage):
 class V(Transformer[Token, Block], ErrorStorage):
    functions = {
       "print": unary_operation(UnaryOperator.Print),
-      "read": unary_operation(UnaryOperator.Read),
+      "put": unary_operation(UnaryOperator.Put),
+      "eprint": unary_operation(UnaryOperator.EPrint),
+      "input": unary_operation(UnaryOperator.Input),
+      "read_line": nary_operation(NaryOperator.ReadLine),
+      "stdin_lines": nary_operation(NaryOperator.StdinLines),
+      "read": overload({1: unary_operation(UnaryOperator.Read), 2: binary_operation(BinaryOperator.Read)}),
+      "open": binary_operation(BinaryOperator.Open),
+      "seek": nary_operation(NaryOperator.Seek),
+      "flush": unary_operation(UnaryOperator.Flush),
+      "close": unary_operation(UnaryOperator.Close),
       "write": binary_operation(BinaryOperator.Write),
       "join": binary_operation(BinaryOperator.Join),
       "type": unary_operation(UnaryOperator.Type),
@@ -60,16 +130,31 @@ class V(Transformer[Token, Block], ErrorStorage):
       "int": unary_operation(UnaryOperator.Int),
       "float": unary_operation(UnaryOperator.Float),
       "str": unary_operation(UnaryOperator.Str),
+      "repr": unary_operation(UnaryOperator.Repr),
       "len": unary_operation(UnaryOperator.Len),
+      "hash": unary_operation(UnaryOperator.Hash),
+      "variant": unary_operation(UnaryOperator.Variant),
       "push": binary_operation(BinaryOperator.Push),
-      "remove": binary_operation(BinaryOperator.Remove),
+      "remove": overload({1: plugin("remove"), 2: binary_operation(BinaryOperator.Remove)}),
       "index": binary_operation(BinaryOperator.Index),
+      "insert": ternary_operation(TernaryOperator.Insert),
+      "extend": binary_operation(BinaryOperator.Extend),
+      "pop": overload({1: unary_operation(UnaryOperator.Pop), 2: binary_operation(BinaryOperator.Remove)}),
+      "clear": unary_operation(UnaryOperator.Clear),
+      "reverse": unary_operation(UnaryOperator.Reverse),
+      "contains": binary_operation(BinaryOperator.Contains),
+      "count": overload({1: plugin("count"), 2: binary_operation(BinaryOperator.Count)}),
+      "slice": nary_operation(NaryOperator.Slice),
+      "copy": overload({1: unary_operation(UnaryOperator.Copy), 2: plugin("copy")}),
+      "deep_copy": unary_operation(UnaryOperator.DeepCopy),
+      "range": nary_operation(NaryOperator.Range),
    }
 
    branch = ternary_operation(TernaryOperator.Branch)
    orbranch = binary_operation(BinaryOperator.Or)
    andbranch = binary_operation(BinaryOperator.And)
    eq = binary_operation(BinaryOperator.Eq)
+   identity = binary_operation(BinaryOperator.Is)
    lt = binary_operation(BinaryOperator.Lt)
    leq = binary_operation(BinaryOperator.Leq)
    leftshift = binary_operation(BinaryOperator.LeftShift)
@@ -81,6 +166,8 @@ class V(Transformer[Token, Block], ErrorStorage):
    sub = binary_operation(BinaryOperator.Sub)
    mul = binary_operation(BinaryOperator.Mul)
    div = binary_operation(BinaryOperator.Div)
+   floordiv = binary_operation(BinaryOperator.FloorDiv)
+   pow = binary_operation(BinaryOperator.Pow)
    modulo = binary_operation(BinaryOperator.Modulo)
    bitnot = unary_operation(UnaryOperator.BitNot)
    knot = unary_operation(UnaryOperator.Not)
@@ -89,6 +176,8 @@ class V(Transformer[Token, Block], ErrorStorage):
 
    exec = flatten
    expr = flatten
+   target = flatten
+   pattern = flatten
 
    def NIL(self, token: Token):
       return Expr.Literal(Literal.Nil())
@@ -100,7 +189,11 @@ class V(Transformer[Token, Block], ErrorStorage):
       return Expr.Literal(Literal.Bool(token == "true"))
 
    def INT(self, token: Token):
-      return Expr.Literal(Literal.Int(int(token)))
+      value = int(token)
+      if -(2**63) <= value < 2**63:
+         return Expr.Literal(Literal.Int(value))
+      # Too large for a int literal, parse it at runtime as a big int.
+      return Expr.UnaryOperation(UnaryOperator.Int, Expr.Literal(Literal.Str(str(value))))
 
    def FLOAT(self, token: Token):
       return Expr.Literal(Literal.Float(float(token)))
@@ -109,9 +202,16 @@ class V(Transformer[Token, Block], ErrorStorage):
       return [Exec.Expr(i) if isinstance(i, ExprT) else i for i in args]
 
    def STRING(self, token: Token):
-      return Expr.Literal(
-         Literal.Str(str(token)[1:-1].replace(r"\"", '"').replace(r"\n", "\n").replace(r"\t", "\t").replace(r"\\", "\\"))
-      )
+      def escape(match: re.Match[str]) -> str:
+         if match[2] is None:
+            return ESCAPES.get(match[1], match[1])
+         codepoint = int(match[2], 16)
+         if 0x10FFFF < codepoint or 0xD800 <= codepoint <= 0xDFFF:
+            self.add_error(f"Invalid unicode escape `\\{match[1]}`", range=Range.from_token(token))
+            return ""
+         return chr(codepoint)
+
+      return Expr.Literal(Literal.Str(re.sub(r"\\(u\{([0-9a-fA-F]+)\}|.)", escape, str(token)[1:-1])))
 
    def var(self, args: tuple[Token]):
       name = args[0]
@@ -119,6 +219,8 @@ class V(Transformer[Token, Block], ErrorStorage):
          return Expr.Reference(Reference.Variable(variable))
       if (function := self.i.functions.get(name)) is not None:
          return Expr.Reference(Reference.Function(function[0]))
+      if (struct := self.i.structs.get(name)) is not None:
+         return Expr.Prototype(struct[0])
       self.add_error(f"Undefined variable `{name}`", range=Range.from_token(name), typo=typo(str(name), self.variables.keys()))
       return False
 
@@ -137,6 +239,10 @@ class V(Transformer[Token, Block], ErrorStorage):
    def ret(self, args: tuple[ExprT]):
       return Exec.Return(args[0])
 
+   def yld(self, args: tuple[ExprT]):
+      self.function.generator = True
+      return Exec.Yield(args[0])
+
    def whilebranch(self, args: list[Any]):
       return Exec.While(args[0], args[1])
 
@@ -159,16 +265,150 @@ class V(Transformer[Token, Block], ErrorStorage):
       return Exec.Branch(args[0], args[1], [self.elsegen(args[2:], otherwise)] if len(args) > 2 else (otherwise or []))
 
    def assign(self, args: tuple[Token, ExprT]):
-      name = str(args[0])
-      variable = self.variables.get(name)
+      return Expr.SetVar(self.variable(args[0]), args[1])
+
+   def unpack(self, args: list[Any]):
+      targets = args[:-1]
+      if len(targets) == 1 and not isinstance(targets[0], Rest):
+         return Expr.Unpack(targets[0], args[-1])
+      return Expr.Unpack(self.target_seq(targets), args[-1])
+
+   def target_var(self, args: tuple[Token]):
+      return Target.Variable(self.variable(args[0]))
+
+   def target_rest(self, args: tuple[Token]):
+      return Rest(self.variable(args[0]), args[0])
+
+   def target_seq(self, args: list[Any]):
+      return Target.Sequence(*self.split_rest(optional_list(args)))
+
+   def split_rest(self, items: list[Any]) -> tuple[list[Any], Any, list[Any]]:
+      """Split a sequence at its rest-capture into the items before, the rest and the items after."""
+      rests = [i for i, item in enumerate(items) if isinstance(item, Rest)]
+      if not rests:
+         return items, None, []
+      for i in rests[1:]:
+         self.add_error("Multiple rest-captures in one pattern", range=Range.from_token(items[i].name))
+      after = [item for item in items[rests[0] + 1 :] if not isinstance(item, Rest)]
+      return items[: rests[0]], items[rests[0]].value, after
+
+   def target_struct(self, args: list[Any]):
+      return Target.Struct(args)
+
+   def matchblock(self, args: list[Any]):
+      return Exec.Match(args[0], args[1:])
+
+   def matcharm(self, args: tuple[PatternT, ExprT | None, Block]):
+      return MatchArm(args[0], args[1], args[2])
+
+   def pattern_literal(self, args: tuple[ExprT]):
+      literal = args[0]
+      if not isinstance(literal, Expr.Literal):
+         self.add_error("Integer literal is too large for a pattern")
+         return Pattern.Wildcard()
+      return Pattern.Literal(literal.literal)
+
+   def pattern_name(self, args: tuple[Token]):
+      name = args[0]
+      if name == "_":
+         return Pattern.Wildcard()
+      if str(name) in TYPE_NAMES or str(name) in self.i.structs:
+         return Pattern.Type(str(name))
+      return Pattern.Binding(self.variable(name), Pattern.Wildcard())
+
+   def pattern_binding(self, args: tuple[PatternT, Token]):
+      return Pattern.Binding(self.variable(args[1]), args[0])
+
+   def pattern_struct(self, args: list[Any]):
+      name = args[0]
+      struct = self.lookup_struct(name)
+      if struct is None:
+         return Pattern.Wildcard()
+      prototype_id, prototype = struct
+      if prototype.variants:
+         self.add_error(f"`{name}` is a enum, match one of its variants instead", range=Range.from_token(name))
+      return Pattern.Struct(prototype_id, self.field_patterns(name, prototype.field_map, args[1:]))
+
+   def pattern_variant(self, args: list[Any]):
+      variant = self.lookup_variant(args[0], args[1])
+      if variant is None:
+         return Pattern.Wildcard()
+      prototype_id, variant_id, field_map = variant
+      fields = self.field_patterns(f"{args[0]}::{args[1]}", field_map, [arg for arg in args[2:] if arg is not None])
+      return Pattern.Struct(prototype_id, fields, variant_id)
+
+   def field_patterns(self, name: str, field_map: dict[int, int], args: list[Any]) -> list[FieldPattern]:
+      fields: list[FieldPattern] = []
+      for field_name, pattern in args:
+         field_id = self.i.ident_map.get(str(field_name))
+         if field_id not in field_map:
+            self.add_error(f"`{name}` has no field `{field_name}`", range=Range.from_token(field_name))
+            continue
+         if pattern is None:
+            pattern = Pattern.Binding(self.variable(field_name), Pattern.Wildcard())
+         fields.append(FieldPattern(field_id, pattern))
+      return fields
+
+   def lookup_struct(self, name: Token) -> tuple[int, Prototype] | None:
+      struct = self.i.structs.get(str(name))
+      if struct is None:
+         self.add_error(f"Undefined struct `{name}`", range=Range.from_token(name), typo=typo(str(name), self.i.structs.keys()))
+      return struct
+
+   def lookup_variant(self, name: Token, variant_name: Token) -> tuple[int, int, dict[int, int]] | None:
+      """Returns the prototype id, variant id and field map of the variant."""
+      struct = self.lookup_struct(name)
+      if struct is None:
+         return None
+      prototype_id, prototype = struct
+      for variant_id, variant in enumerate(prototype.variants):
+         if variant.name == variant_name:
+            return prototype_id, variant_id, variant.field_map
+      self.add_error(
+         f"`{name}` has no variant `{variant_name}`",
+         range=Range.from_token(variant_name),
+         typo=typo(str(variant_name), (variant.name for variant in prototype.variants)),
+      )
+      return None
+
+   def pattern_field(self, args: tuple[Token, PatternT | None]):
+      return args[0], args[1]
+
+   def pattern_rest(self, args: tuple[Token]):
+      return Rest(self.pattern_name(args), args[0])
+
+   def pattern_list(self, args: list[Any]):
+      return Pattern.Sequence(False, *self.split_rest(optional_list(args)))
+
+   def pattern_tuple(self, args: list[Any]):
+      return Pattern.Sequence(True, *self.split_rest(args))
+
+   def target_field(self, args: tuple[Token, TargetT | None]):
+      name = args[0]
+      target = args[1] if args[1] is not None else Target.Variable(self.variable(name))
+      field_id = self.i.ident_map.get(str(name))
+      if field_id is None:
+         self.add_error(f"Undefined field `{name}`", range=Range.from_token(name))
+         field_id = 0
+      return FieldTarget(field_id, target)
+
+   def variable(self, name: Token) -> ReferenceT:
+      """Returns the variable called name, declaring it if it does not exist."""
+      variable = self.variables.get(str(name))
       if variable is None:
          variable = len(self.variables)
-         self.variables[name] = len(self.variables)
-      return Expr.SetVar(Reference.Variable(variable), args[1])
+         self.variables[str(name)] = variable
+      return Reference.Variable(variable)
 
    def neq(self, args: tuple[ExprT, ExprT]):
       return Expr.UnaryOperation(UnaryOperator.Not, self.eq(args))
 
+   def contains(self, args: tuple[ExprT, ExprT]):
+      return Expr.BinaryOperation(BinaryOperator.Contains, args[1], args[0])
+
+   def notin(self, args: tuple[ExprT, ExprT]):
+      return Expr.UnaryOperation(UnaryOperator.Not, self.contains(args))
+
    def gt(self, args: tuple[ExprT, ExprT]):
       return Expr.UnaryOperation(UnaryOperator.Not, self.leq(args))
 
@@ -180,17 +420,20 @@ class V(Transformer[Token, Block], ErrorStorage):
       qualname = str(name)
       return self.__call(name, qualname, args[1:] if len(args) >= 1 and isinstance(args[0], Token) else args)
 
-   def __call(self, name: Token, qualname: str, args: list[ExprT]):
-      if args[-1] is None:  # type: ignore
+   def __call(self, name: Token, qualname: str, args: list[Any]):
+      if args and args[-1] is None:  # type: ignore
          args.pop()
+      args, keywords = self.split_keywords(args)
       if generator := self.functions.get(qualname):
+         if keywords:
+            self.add_error(f"`{qualname}` does not take keyword arguments", range=Range.from_token(keywords[0].name))
          return generator(self, args)  # type: ignore
       elif (variable := self.variables.get(qualname)) is not None:
-         return Expr.Call(Expr.Reference(Reference.Variable(variable)), args)
+         return Expr.Call(Expr.Reference(Reference.Variable(variable)), args, self.keyword_arguments(keywords))
       elif function := self.i.functions.get(qualname):
-         return Expr.Call(Expr.Reference(Reference.Function(function[0])), args)
+         return Expr.Call(Expr.Reference(Reference.Function(function[0])), args, self.keyword_arguments(keywords))
       elif plugin := PLUGINS.get(qualname):
-         return Expr.Plugin(plugin.id, args)
+         return Expr.Plugin(plugin.id, self.plugin_arguments(plugin, args, keywords))
       else:
          self.add_error(
             f"Undefined function `{qualname}`",
@@ -199,6 +442,42 @@ class V(Transformer[Token, Block], ErrorStorage):
          )
       return Expr.Literal(Literal.Nil())
 
+   def kwarg(self, args: tuple[Token, ExprT]):
+      return Keyword(args[0], args[1])
+
+   def split_keywords(self, args: list[Any]) -> tuple[list[ExprT], list[Keyword]]:
+      """Split the arguments of a call into the positional arguments and the keyword arguments after them."""
+      positional = [arg for arg in args if not isinstance(arg, Keyword)]
+      keywords = [arg for arg in args if isinstance(arg, Keyword)]
+      if any(isinstance(arg, Keyword) for arg in args[: len(positional)]):
+         self.add_error("Positional argument follows keyword argument", range=Range.from_token(keywords[0].name))
+      names: set[str] = set()
+      for keyword in keywords:
+         if keyword.name in names:
+            self.add_error(f"Keyword argument `{keyword.name}` is given more than once", range=Range.from_token(keyword.name))
+         names.add(str(keyword.name))
+      return positional, keywords
+
+   def keyword_arguments(self, keywords: list[Keyword]) -> list[KeywordArgument]:
+      return [KeywordArgument(str(keyword.name), keyword.value) for keyword in keywords]
+
+   def plugin_arguments(self, plugin: Plugin, args: list[ExprT], keywords: list[Keyword]) -> list[ExprT]:
+      """Place keyword arguments at the position of their parameter, skipped parameters are nil."""
+      arguments: list[ExprT | None] = list(args)
+      for keyword in keywords:
+         if keyword.name not in plugin.parameters:
+            self.add_error(f"`{plugin.name}` has no parameter `{keyword.name}`", range=Range.from_token(keyword.name))
+            continue
+         index = plugin.parameters.index(keyword.name)
+         if index < len(arguments) and arguments[index] is not None:
+            self.add_error(
+               f"Argument `{keyword.name}` to `{plugin.name}` is given more than once", range=Range.from_token(keyword.name)
+            )
+            continue
+         arguments.extend([None] * (index + 1 - len(arguments)))
+         arguments[index] = keyword.value
+      return [Expr.Literal(Literal.Nil()) if argument is None else argument for argument in arguments]
+
    def chain(self, args: list[ExprT]):
       acc = args[0]
       func = cast(Token, args[1])
@@ -208,24 +487,57 @@ class V(Transformer[Token, Block], ErrorStorage):
    def vchain(self, args: list[ExprT]):
       acc = args[0]
       func = args[1]
-      params = args[2:]
-      return Expr.Call(func, [acc, *params])
+      params, keywords = self.split_keywords(optional_list(args[2:]))
+      return Expr.Call(func, [acc, *params], self.keyword_arguments(keywords))
 
    def vcall(self, args: list[ExprT]):
       callable = args[0]
-      params = optional_list(args[1:])
-      return Expr.Call(callable, params)
+      params, keywords = self.split_keywords(optional_list(args[1:]))
+      return Expr.Call(callable, params, self.keyword_arguments(keywords))
 
    def struct(self, args: list[Any]):
-      prototype_id, prototype = self.i.structs[str(args[0])]
-      it = iter(args[1:])
-      values = {}
+      name = args[0]
+      struct = self.lookup_struct(name)
+      if struct is None:
+         return Expr.Literal(Literal.Nil())
+      prototype_id, prototype = struct
+      if prototype.variants:
+         self.add_error(f"`{name}` is a enum, construct one of its variants instead", range=Range.from_token(name))
+      values = self.struct_values(name, str(name), prototype.field_map, prototype.defaults, args[1:])
+      return Expr.Struct(prototype_id, values)
+
+   def enumstruct(self, args: list[Any]):
+      variant = self.lookup_variant(args[0], args[1])
+      if variant is None:
+         return Expr.Literal(Literal.Nil())
+      prototype_id, variant_id, field_map = variant
+      values = self.struct_values(args[1], f"{args[0]}::{args[1]}", field_map, {}, args[2:])
+      return Expr.Struct(prototype_id, values, variant_id)
+
+   def struct_values(
+      self, token: Token, name: str, field_map: dict[int, int], defaults: dict[int, ExprT], args: list[Any]
+   ) -> list[ExprT | None]:
+      """Returns the field values of a struct literal in the order of the field map, None for default values."""
+      it = iter(args)
+      values: dict[int, ExprT | None] = {}
       for field_name, field_expr in zip(it, it):
          if field_name is None:
             continue
-         field_qualname = str(field_name)
-         values[prototype.field_map[self.i.ident_map[field_qualname]]] = field_expr
-      return Expr.Struct(prototype_id, [values[field] for field in prototype.field_map.values()])
+         field_id = self.i.ident_map.get(str(field_name))
+         if field_id not in field_map:
+            self.add_error(f"`{name}` has no field `{field_name}`", range=Range.from_token(field_name))
+            continue
+         values[field_map[field_id]] = field_expr
+      field_names = {id: field_name for field_name, id in self.i.ident_map.items()}
+      for field_id, index in field_map.items():
+         if index in values:
+            continue
+         if field_id in defaults:
+            values[index] = None
+         else:
+            self.add_error(f"Missing field `{field_names[field_id]}` of `{name}`", range=Range.from_token(token))
+            values[index] = Expr.Literal(Literal.Nil())
+      return [values[index] for index in field_map.values()]
 
    def setfield(self, args: list[Any]):
       instance = args[0]
@@ -240,6 +552,25 @@ class V(Transformer[Token, Block], ErrorStorage):
       field_qualname = str(field_name)
       return Expr.GetField(instance, self.i.ident_map[field_qualname])
 
+   def superfield(self, args: tuple[Token]):
+      name = args[0]
+      struct = self.i.structs.get(self.function.name.partition(".")[0]) if "." in self.function.name else None
+      if struct is None or struct[1].parent is None:
+         self.add_error("`super` can only be used in methods of a struct with a parent", range=Range.from_token(name))
+         return Expr.Literal(Literal.Nil())
+      if not self.function.parameters:
+         self.add_error("`super` used in a static method", range=Range.from_token(name))
+         return Expr.Literal(Literal.Nil())
+      method_id = self.i.ident_map.get(str(name))
+      prototypes = [prototype for _, prototype in self.i.structs.values()]
+      prototype = prototypes[struct[1].parent]
+      while method_id not in prototype.method_map:
+         if prototype.parent is None:
+            self.add_error(f"Parent of `{struct[1].name}` has no method `{name}`", range=Range.from_token(name))
+            return Expr.Literal(Literal.Nil())
+         prototype = prototypes[prototype.parent]
+      return Expr.Super(struct[1].parent, method_id, Expr.Reference(Reference.Variable(0)))
+
    def die(self, args: tuple[ExprT]):
       expr = args[0]
       return Expr.Die(expr, IRRange(0, 0, 0, 0))
@@ -248,6 +579,14 @@ class V(Transformer[Token, Block], ErrorStorage):
       expr = args[0]
       return Expr.OrDie(expr, IRRange(0, 0, 0, 0))
 
+   def slice(self, args: list[Any]):
+      args = [Expr.Literal(Literal.Nil()) if arg is None else arg for arg in args]
+      return Expr.NaryOperation(NaryOperator.Slice, args)
+
+   def tuple(self, args: list[Any]):
+      return Expr.NaryOperation(NaryOperator.Tuple, args)
+
+   # Keep below the other rules, it shadows the builtin list type in the class body.
    def list(self, args: list[Any]):
       args = optional_list(args)
       return Expr.NaryOperation(NaryOperator.List, args)
@@ -100,20 +100,59 @@ class Highlighter(Visitor):
       name = cast(Token, node.children[0])
       self.add_token_for_highlighting(name, cls="function")
       for token in optional_list(node.children[2:-2]):
-         self.add_token_for_highlighting(token, cls="parameter")
+         if isinstance(token, Token) and token.type == "IDENT":
+            self.add_token_for_highlighting(token, cls="parameter")
+
+   def param_default(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[0]), cls="parameter")
+
+   def param_rest(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[1]), cls="parameter")
+
+   def kwarg(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[0]), cls="parameter")
 
    def structdef(self, node: Tree):
       children = iter(node.children)
       self.add_token_for_highlighting(cast(Token, next(children)), cls="struct")
       for child in children:
-         if child is None or isinstance(child, lark.Tree):  # type: ignore
+         if isinstance(child, lark.Tree):
             break
-         self.add_token_for_highlighting(child, cls="field")
+         if child is None:  # type: ignore
+            continue
+         if child == ":":
+            self.add_token_for_highlighting(cast(Token, next(children)), cls="struct")
+
+   def structfield(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[0]), cls="field")
+
+   def constdef(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[0]), cls="keyword")
+      self.add_token_for_highlighting(cast(Token, node.children[1]), cls="field")
+
+   def enumdef(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[0]), cls="keyword")
+      self.add_token_for_highlighting(cast(Token, node.children[1]), cls="struct")
+
+   def variant(self, node: Tree):
+      children = iter(node.children)
+      self.add_token_for_highlighting(cast(Token, next(children)), cls="struct")
+      for child in children:
+         if isinstance(child, Token) and child.type == "IDENT":
+            self.add_token_for_highlighting(child, cls="field")
+
+   def enumstruct(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[0]), cls="struct")
+      self.add_token_for_highlighting(cast(Token, node.children[2]), cls="struct")
 
    def ret(self, node: Tree):
       keyword = cast(Token, node.children[0])
       self.add_token_for_highlighting(keyword, cls="keyword")
 
+   def yld(self, node: Tree):
+      keyword = cast(Token, node.children[0])
+      self.add_token_for_highlighting(keyword, cls="keyword")
+
    def eval(self, node: Tree):
       keyword = cast(Token, node.children[0])
       self.add_token_for_highlighting(keyword, cls="keyword")
@@ -160,6 +199,20 @@ class Highlighter(Visitor):
          next(children)
          next(children, None)
 
+   def matchblock(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[0]), cls="keyword")
+
+   def matcharm(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[0]), cls="keyword")
+      if node.children[2] == "if":
+         self.add_token_for_highlighting(cast(Token, node.children[2]), cls="keyword")
+
+   def pattern_binding(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[1]), cls="keyword")
+
+   def pattern_struct(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[0]), cls="struct")
+
    def struct(self, node: Tree):
       children = iter(node.children)
       keyword = cast(Token, next(children))
@@ -189,6 +242,13 @@ class Highlighter(Visitor):
    def identity(self, node: Tree):
       self.add_token_for_highlighting(cast(Token, node.children[1]), cls="operator")
 
+   def contains(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[1]), cls="operator")
+
+   def notin(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[1]), cls="operator")
+      self.add_token_for_highlighting(cast(Token, node.children[2]), cls="operator")
+
    def knot(self, node: Tree):
       self.add_token_for_highlighting(cast(Token, node.children[0]), cls="operator")
 
@@ -199,6 +259,10 @@ class Highlighter(Visitor):
    def setfield(self, node: Tree):
       self.add_token_for_highlighting(cast(Token, node.children[2]), cls="field")
 
+   def superfield(self, node: Tree):
+      self.add_token_for_highlighting(cast(Token, node.children[0]), cls="keyword")
+      self.add_token_for_highlighting(cast(Token, node.children[2]), cls="field")
+
    def getfield(self, node: Tree):
       name = cast(Token, node.children[2])
       self.add_token_for_highlighting(name, cls="field")
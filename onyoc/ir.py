@@ -1,4 +1,4 @@
-from dataclasses import dataclass
+from dataclasses import dataclass, field
 from enum import auto
 
 from .serde import *
@@ -36,10 +36,18 @@ class IRRange(Struct_):
 class ReservedIdents(Struct_):
    next: int
    __call__: int
+   __contains__: int | None = None
+   # Not called __init__, it would replace the constructor of the dataclass.
+   init: int | None = None
 
    @staticmethod
    def from_ident_map(ident_map: dict[str, int]):
-      return ReservedIdents(next=ident_map.get("next", 0), __call__=ident_map.get("__call__", 0))
+      return ReservedIdents(
+         next=ident_map.get("next", 0),
+         __call__=ident_map.get("__call__", 0),
+         __contains__=ident_map.get("__contains__"),
+         init=ident_map.get("__init__"),
+      )
 
 
 @dataclass
@@ -48,6 +56,9 @@ class Function(Struct_):
    parameters: list[str]
    variables: list[str]
    body: Block
+   generator: bool = False
+   defaults: dict[int, "ExprT"] = field(default_factory=dict)
+   rest: bool = False
 
 
 @dataclass
@@ -55,6 +66,16 @@ class Prototype(Struct_):
    name: str
    field_map: dict[int, int]
    method_map: dict[int, int]
+   variants: list["Variant"] = field(default_factory=list)
+   parent: int | None = None
+   defaults: dict[int, "ExprT"] = field(default_factory=dict)
+   constants: dict[int, "ExprT"] = field(default_factory=dict)
+
+
+@dataclass
+class Variant(Struct_):
+   name: str
+   field_map: dict[int, int]
 
 
 class Literal(ExternallyTaggedEnum):
@@ -93,9 +114,22 @@ class UnaryOperator(Enum):
    Int = auto()
    Float = auto()
    Str = auto()
+   Repr = auto()
    Len = auto()
    Print = auto()
+   Put = auto()
+   EPrint = auto()
+   Input = auto()
    Read = auto()
+   Flush = auto()
+   Close = auto()
+   Hash = auto()
+   Pop = auto()
+   Clear = auto()
+   Reverse = auto()
+   Copy = auto()
+   DeepCopy = auto()
+   Variant = auto()
 
 
 class BinaryOperator(Enum):
@@ -103,6 +137,8 @@ class BinaryOperator(Enum):
    Sub = auto()
    Mul = auto()
    Div = auto()
+   FloorDiv = auto()
+   Pow = auto()
    Modulo = auto()
    GetItem = auto()
    Eq = auto()
@@ -121,16 +157,27 @@ class BinaryOperator(Enum):
    Index = auto()
    Join = auto()
    Write = auto()
+   Open = auto()
+   Read = auto()
+   Extend = auto()
+   Contains = auto()
+   Count = auto()
 
 
 class TernaryOperator(Enum):
    Branch = auto()
    SetItem = auto()
+   Insert = auto()
 
 
 class NaryOperator(Enum):
    Tuple = auto()
    List = auto()
+   ReadLine = auto()
+   StdinLines = auto()
+   Seek = auto()
+   Range = auto()
+   Slice = auto()
 
 
 class ReferenceT:
@@ -147,10 +194,91 @@ class Reference(ExternallyTaggedEnum):
       _: int
 
 
+class TargetT:
+   ...
+
+
+class Target(InternallyTaggedEnum):
+   @dataclass
+   class Variable(TargetT, Struct_):
+      variable: ReferenceT
+
+   @dataclass
+   class Sequence(TargetT, Struct_):
+      before: list[TargetT]
+      rest: ReferenceT | None
+      after: list[TargetT]
+
+   @dataclass
+   class Struct(TargetT, Struct_):
+      fields: list["FieldTarget"]
+
+
+@dataclass
+class FieldTarget(Struct_):
+   field_id: int
+   target: TargetT
+
+
 class ExprT:
    ...
 
 
+class PatternT:
+   ...
+
+
+class Pattern(InternallyTaggedEnum):
+   @dataclass
+   class Wildcard(PatternT, Struct_):
+      pass
+
+   @dataclass
+   class Literal(PatternT, Struct_):
+      literal: LiteralT
+
+   @dataclass
+   class Type(PatternT, Struct_):
+      name: str
+
+   @dataclass
+   class Binding(PatternT, Struct_):
+      variable: ReferenceT
+      pattern: PatternT
+
+   @dataclass
+   class Struct(PatternT, Struct_):
+      prototype: int
+      fields: list["FieldPattern"]
+      variant: int | None = None
+
+   @dataclass
+   class Sequence(PatternT, Struct_):
+      tuple: bool
+      before: list[PatternT]
+      rest: PatternT | None
+      after: list[PatternT]
+
+
+@dataclass
+class FieldPattern(Struct_):
+   field_id: int
+   pattern: PatternT
+
+
+@dataclass
+class KeywordArgument(Struct_):
+   name: str
+   expr: ExprT
+
+
+@dataclass
+class MatchArm(Struct_):
+   pattern: PatternT
+   guard: ExprT | None
+   block: Block
+
+
 class Exec(InternallyTaggedEnum):
    @dataclass
    class While(ExecT, Struct_):
@@ -174,10 +302,19 @@ class Exec(InternallyTaggedEnum):
       then: Block
       otherwise: Block
 
+   @dataclass
+   class Match(ExecT, Struct_):
+      expr: ExprT
+      arms: list["MatchArm"]
+
    @dataclass
    class Return(ExecT, Struct_):
       expr: ExprT
 
+   @dataclass
+   class Yield(ExecT, Struct_):
+      expr: ExprT
+
    @dataclass
    class Expr(ExecT, Struct_):
       expr: ExprT
@@ -219,6 +356,7 @@ class Expr(InternallyTaggedEnum):
    class Call(ExprT, Struct_):
       callable: ExprT
       parameters: list[ExprT]
+      keywords: list["KeywordArgument"] = field(default_factory=list)
 
    @dataclass
    class Plugin(ExprT, Struct_):
@@ -228,13 +366,19 @@ class Expr(InternallyTaggedEnum):
    @dataclass
    class Struct(ExprT, Struct_):
       prototype: int
-      values: list[ExprT]
+      values: list[ExprT | None]
+      variant: int | None = None
 
    @dataclass
    class SetVar(ExprT, Struct_):
       variable: ReferenceT
       expr: ExprT
 
+   @dataclass
+   class Unpack(ExprT, Struct_):
+      target: TargetT
+      expr: ExprT
+
    @dataclass
    class SetField(ExprT, Struct_):
       instance: ExprT
@@ -246,6 +390,17 @@ class Expr(InternallyTaggedEnum):
       instance: ExprT
       field_id: int
 
+   @dataclass
+   class Super(ExprT, Struct_):
+      prototype: int
+      method_id: int
+      instance: ExprT
+
+   @dataclass
+   class Prototype(ExprT, Struct_):
+      prototype: int
+      variant: int | None = None
+
    @dataclass
    class Die(ExprT, Struct_):
       expr: ExprT
@@ -266,4 +421,51 @@ class Plugin:
 
 PLUGINS = {
    "split": Plugin(0, "split", ["string", "separator"]),
+   "exists": Plugin(1, "exists", ["path"]),
+   "is_dir": Plugin(2, "is_dir", ["path"]),
+   "list_dir": Plugin(3, "list_dir", ["path"]),
+   "mkdir": Plugin(4, "mkdir", ["path", "recursive"]),
+   "remove": Plugin(5, "remove", ["path"]),
+   "rename": Plugin(6, "rename", ["from", "to"]),
+   "copy": Plugin(7, "copy", ["from", "to"]),
+   "stat": Plugin(8, "stat", ["path"]),
+   "glob": Plugin(9, "glob", ["pattern"]),
+   "path_join": Plugin(10, "path_join", ["*paths"]),
+   "basename": Plugin(11, "basename", ["path"]),
+   "dirname": Plugin(12, "dirname", ["path"]),
+   "extension": Plugin(13, "extension", ["path"]),
+   "with_extension": Plugin(14, "with_extension", ["path", "extension"]),
+   "normalize": Plugin(15, "normalize", ["path"]),
+   "absolute": Plugin(16, "absolute", ["path"]),
+   "map": Plugin(17, "map", ["iterable", "func"]),
+   "filter": Plugin(18, "filter", ["iterable", "func"]),
+   "zip": Plugin(19, "zip", ["iterable1", "iterable2"]),
+   "enumerate": Plugin(20, "enumerate", ["iterable"]),
+   "chain": Plugin(21, "chain", ["iterable1", "iterable2"]),
+   "take": Plugin(22, "take", ["iterable", "count"]),
+   "skip": Plugin(23, "skip", ["iterable", "count"]),
+   "step_by": Plugin(24, "step_by", ["iterable", "step"]),
+   "take_while": Plugin(25, "take_while", ["iterable", "func"]),
+   "flat_map": Plugin(26, "flat_map", ["iterable", "func"]),
+   "collect": Plugin(27, "collect", ["iterable"]),
+   "sum": Plugin(28, "sum", ["iterable", "start"]),
+   "any": Plugin(29, "any", ["iterable", "func"]),
+   "all": Plugin(30, "all", ["iterable", "func"]),
+   "reduce": Plugin(31, "reduce", ["iterable", "func", "initial"]),
+   "sort": Plugin(32, "sort", ["list", "key", "reverse", "compare"]),
+   "sorted": Plugin(33, "sorted", ["iterable", "key", "reverse", "compare"]),
+   "count": Plugin(34, "count", ["iterable"]),
+   "fields": Plugin(35, "fields", ["instance"]),
+   "methods": Plugin(36, "methods", ["instance"]),
+   "getattr": Plugin(37, "getattr", ["instance", "name"]),
+   "setattr": Plugin(38, "setattr", ["instance", "name", "value"]),
+   "hasattr": Plugin(39, "hasattr", ["instance", "name"]),
+   "prototype": Plugin(40, "prototype", ["value"]),
+   "new": Plugin(41, "new", ["prototype", "fields"]),
+   "name_of": Plugin(42, "name_of", ["func"]),
+   "params_of": Plugin(43, "params_of", ["func"]),
+   "arity": Plugin(44, "arity", ["func"]),
+   "self_of": Plugin(45, "self_of", ["method"]),
+   "bind": Plugin(46, "bind", ["func", "instance"]),
+   "partial": Plugin(47, "partial", ["func", "arguments"]),
 }
@@ -0,0 +1,97 @@
+use std::{
+   fs::{File, OpenOptions},
+   io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write}
+};
+
+use crate::misc::trim_newline;
+
+/// A file opened by the open builtin. Reads are buffered, writes are not, so
+/// the file can be closed by dropping the handle without losing data.
+#[derive(Debug)]
+pub struct FileHandle {
+   pub path: String,
+   /// None after the file has been closed.
+   reader: Option<BufReader<File>>
+}
+
+impl FileHandle {
+   /// Open file at path, mode is one of the C fopen() modes without `b`.
+   pub fn open(path: &str, mode: &str) -> io::Result<FileHandle> {
+      let mut options = OpenOptions::new();
+      match mode {
+         "r" => options.read(true),
+         "w" => options.write(true).create(true).truncate(true),
+         "a" => options.append(true).create(true),
+         "r+" => options.read(true).write(true),
+         "w+" => options.read(true).write(true).create(true).truncate(true),
+         "a+" => options.read(true).append(true).create(true),
+         _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid file mode {mode:?}.")))
+      };
+      Ok(FileHandle { path: path.to_owned(), reader: Some(BufReader::new(options.open(path)?)) })
+   }
+
+   fn reader(&mut self) -> io::Result<&mut BufReader<File>> {
+      self.reader.as_mut().ok_or_else(|| io::Error::other("File is closed."))
+   }
+
+   /// Returns None on EOF.
+   pub fn read_line(&mut self) -> io::Result<Option<String>> {
+      let mut line = String::new();
+      if self.reader()?.read_line(&mut line)? == 0 {
+         return Ok(None);
+      }
+      trim_newline(&mut line);
+      Ok(Some(line))
+   }
+
+   pub fn read_to_end(&mut self) -> io::Result<String> {
+      let mut str = String::new();
+      self.reader()?.read_to_string(&mut str)?;
+      Ok(str)
+   }
+
+   /// Read atmost count characters.
+   pub fn read_chars(&mut self, count: usize) -> io::Result<String> {
+      let reader = self.reader()?;
+      let mut bytes = vec![];
+      let mut chars = 0;
+      while let Some(&byte) = reader.fill_buf()?.first() {
+         // Stop at the first byte of the character after the last one.
+         if byte & 0b1100_0000 != 0b1000_0000 {
+            if chars == count {
+               break;
+            }
+            chars += 1;
+         }
+         bytes.push(byte);
+         reader.consume(1);
+      }
+      String::from_utf8(bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+   }
+
+   pub fn write(&mut self, str: &str) -> io::Result<()> {
+      let reader = self.reader()?;
+      // Seeking discards the read buffer so that the write happens at the
+      // logical position instead of after the read-ahead. Only seek when
+      // something was read ahead, pipes and terminals cannot be seeked.
+      if !reader.buffer().is_empty() {
+         let position = reader.stream_position()?;
+         reader.seek(SeekFrom::Start(position))?;
+      }
+      reader.get_mut().write_all(str.as_bytes())
+   }
+
+   pub fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+      self.reader()?.seek(position)
+   }
+
+   pub fn flush(&mut self) -> io::Result<()> {
+      self.reader()?.get_mut().flush()
+   }
+
+   pub fn close(&mut self) -> io::Result<()> {
+      self.reader()?;
+      self.reader = None;
+      Ok(())
+   }
+}
@@ -15,6 +15,43 @@ pub struct Data {
    pub files: Vec<String>
 }
 
+impl Data {
+   /// Returns the function of the method, looking through the parents of the
+   /// prototype if it does not define it.
+   pub fn resolve_method(&self, mut prototype: usize, method_id: usize) -> Option<usize> {
+      loop {
+         let current = &self.prototypes[prototype];
+         if let Some(&function_id) = current.method_map.get(&method_id) {
+            return Some(function_id);
+         }
+         prototype = current.parent?;
+      }
+   }
+
+   /// Returns the prototype which declares the constant, looking through the
+   /// parents of the prototype if it does not declare it.
+   pub fn resolve_constant(&self, mut prototype: usize, constant_id: usize) -> Option<usize> {
+      loop {
+         let current = &self.prototypes[prototype];
+         if current.constants.contains_key(&constant_id) {
+            return Some(prototype);
+         }
+         prototype = current.parent?;
+      }
+   }
+
+   /// Returns true if prototype is ancestor or one of its children.
+   pub fn inherits(&self, mut prototype: usize, ancestor: usize) -> bool {
+      loop {
+         if prototype == ancestor {
+            return true;
+         }
+         let Some(parent) = self.prototypes[prototype].parent else { return false };
+         prototype = parent;
+      }
+   }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Range {
    /// Index for Data.files
@@ -24,13 +61,20 @@ pub struct Range {
    /// 0-indexed column number
    pub col: usize,
    /// length of token
+   #[allow(dead_code)]
    pub len: usize
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ReservedIdents {
    pub next: usize,
-   pub __call__: usize
+   pub __call__: usize,
+   /// None if no struct defines a __contains__ method.
+   #[serde(default)]
+   pub __contains__: Option<usize>,
+   /// Ident id of __init__, None if no struct defines a __init__ method.
+   #[serde(default)]
+   pub init: Option<usize>
 }
 
 #[derive(Debug, Deserialize)]
@@ -39,7 +83,30 @@ pub struct Prototype {
    /// field ident id -> index for Struct.values
    pub field_map: BTreeMap<usize, usize>,
    /// method ident id -> index for Data.functions
-   pub method_map: BTreeMap<usize, usize>
+   pub method_map: BTreeMap<usize, usize>,
+   /// Variants of an enum, every variant has its own fields. Empty for
+   /// structs.
+   #[serde(default)]
+   pub variants: Vec<Variant>,
+   /// Index for Data.prototypes. The fields of the parent are already in
+   /// field_map, methods are looked up in the parent if not in method_map.
+   #[serde(default)]
+   pub parent: Option<usize>,
+   /// field ident id -> value of the field if it is missing from a literal,
+   /// including the defaults of the parents.
+   #[serde(default)]
+   pub defaults: BTreeMap<usize, Expr>,
+   /// constant ident id -> value of the constant, evaluated once when it is
+   /// first accessed.
+   #[serde(default)]
+   pub constants: BTreeMap<usize, Expr>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Variant {
+   pub name: String,
+   /// field ident id -> index for Struct.values
+   pub field_map: BTreeMap<usize, usize>
 }
 
 #[derive(Debug, Deserialize)]
@@ -47,7 +114,17 @@ pub struct Function {
    pub name: String,
    pub parameters: Vec<String>,
    pub variables: Vec<String>,
-   pub body: Block
+   pub body: Block,
+   /// Functions which contain a yield statement return a generator when
+   /// called, instead of running the body.
+   #[serde(default)]
+   pub generator: bool,
+   /// parameter index -> value of the parameter if it is not given.
+   #[serde(default)]
+   pub defaults: BTreeMap<usize, Expr>,
+   /// The last parameter collects the extra arguments into a list.
+   #[serde(default)]
+   pub rest: bool
 }
 
 /// Literal values for primitive data-types
@@ -73,9 +150,22 @@ pub enum UnaryOperator {
    Int,
    Float,
    Str,
+   Repr,
    Len,
    Print,
-   Read
+   Put,
+   EPrint,
+   Input,
+   Read,
+   Flush,
+   Close,
+   Hash,
+   Pop,
+   Clear,
+   Reverse,
+   Copy,
+   DeepCopy,
+   Variant
 }
 
 /// Operators which take 2 parameters
@@ -85,6 +175,8 @@ pub enum BinaryOperator {
    Sub,
    Mul,
    Div,
+   FloorDiv,
+   Pow,
    Modulo,
    GetItem,
    Eq,
@@ -102,20 +194,32 @@ pub enum BinaryOperator {
    Remove,
    Index,
    Join,
-   Write
+   Write,
+   Open,
+   Read,
+   Extend,
+   Contains,
+   Count
 }
 
 /// Operators which take 3 parameters
 #[derive(Debug, Deserialize)]
 pub enum TernaryOperator {
    Branch,
-   SetItem
+   SetItem,
+   Insert
 }
 
 /// Operators which take any no. of parameters
 #[derive(Debug, Deserialize)]
 pub enum NaryOperator {
-   List
+   Tuple,
+   List,
+   ReadLine,
+   StdinLines,
+   Seek,
+   Range,
+   Slice
 }
 
 /// Reference stores index to variables or functions
@@ -148,9 +252,19 @@ pub enum Exec {
       then: Block,
       otherwise: Block
    },
+   /// Run the block of the first arm whose pattern matches the value of expr
+   /// and whose guard is truthy.
+   Match {
+      expr: Expr,
+      arms: Vec<MatchArm>
+   },
    Return {
       expr: Expr
    },
+   /// Suspend the generator and return the value from next.
+   Yield {
+      expr: Expr
+   },
    /// Statement comprising of a expression whose evaluated value is immediately
    /// dropped.
    Expr {
@@ -167,12 +281,93 @@ pub enum Expr {
    BinaryOperation { operator: BinaryOperator, left: Box<Expr>, right: Box<Expr> },
    TernaryOperation { operator: TernaryOperator, first: Box<Expr>, second: Box<Expr>, third: Box<Expr> },
    NaryOperation { operator: NaryOperator, parameters: Vec<Expr> },
-   Call { callable: Box<Expr>, parameters: Vec<Expr> },
+   // keywords are the arguments matched to the parameters by name, after
+   // parameters.
+   Call { callable: Box<Expr>, parameters: Vec<Expr>, keywords: Vec<KeywordArgument> },
    Plugin { id: usize, parameters: Vec<Expr> },
-   Struct { prototype: usize, values: Vec<Expr> },
+   // variant is the index for Prototype.variants if the prototype is an enum,
+   // values are None for fields which take their default value.
+   Struct { prototype: usize, variant: Option<usize>, values: Vec<Option<Expr>> },
    SetVar { variable: Reference, expr: Box<Expr> },
+   // Destructure the value into the variables of the target.
+   Unpack { target: Target, expr: Box<Expr> },
    SetField { instance: Box<Expr>, field_id: usize, value: Box<Expr> },
    GetField { instance: Box<Expr>, field_id: usize },
+   // Method of a parent bound to instance, the method is looked up starting
+   // from prototype instead of the prototype of instance.
+   Super { prototype: usize, method_id: usize, instance: Box<Expr> },
+   // Name of a struct or enum used as a value.
+   Prototype { prototype: usize, variant: Option<usize> },
    Die { expr: Box<Expr>, range: Range },
    OrDie { expr: Box<Expr>, range: Range }
 }
+
+/// Assignment target of Expr::Unpack
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum Target {
+   Variable {
+      variable: Reference
+   },
+   /// Items of a tuple or list. The items between before and after are
+   /// collected into a list and assigned to rest.
+   Sequence {
+      before: Vec<Target>,
+      rest: Option<Reference>,
+      after: Vec<Target>
+   },
+   Struct {
+      fields: Vec<FieldTarget>
+   }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FieldTarget {
+   pub field_id: usize,
+   pub target: Target
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeywordArgument {
+   pub name: String,
+   pub expr: Expr
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MatchArm {
+   pub pattern: Pattern,
+   pub guard: Option<Expr>,
+   pub block: Block
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum Pattern {
+   /// Matches any value.
+   Wildcard,
+   /// Matches values equal to the literal.
+   Literal { literal: Literal },
+   /// Matches values whose type name is name, this includes instances of the
+   /// prototype called name.
+   Type { name: String },
+   /// Assigns the value to variable if the pattern matches.
+   Binding { variable: Reference, pattern: Box<Pattern> },
+   /// Matches instances of the prototype or its children whose fields match. If
+   /// variant is set, the instance must also be of that variant.
+   Struct {
+      prototype: usize,
+      #[serde(default)]
+      variant: Option<usize>,
+      fields: Vec<FieldPattern>
+   },
+   /// Matches lists, or tuples if tuple is set. Without a rest pattern the
+   /// length must be the same, otherwise the items between before and after
+   /// are collected into a list which must match rest.
+   Sequence { tuple: bool, before: Vec<Pattern>, rest: Option<Box<Pattern>>, after: Vec<Pattern> }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FieldPattern {
+   pub field_id: usize,
+   pub pattern: Pattern
+}
@@ -0,0 +1,466 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+   file::FileHandle,
+   ir::{Data, Expr},
+   misc::range_len,
+   plugins::arguments,
+   state::{call_function, call_value, Frame, State},
+   value::Value
+};
+
+/// Iterators implemented by the interpreter, they follow the same protocol as
+/// iterator structs and return iterend when exhausted.
+#[derive(Debug)]
+pub enum NativeIterator {
+   /// Lines read from stdin, without the trailing newline. Stops after the
+   /// first error like FileLines.
+   StdinLines {
+      failed: bool
+   },
+   List {
+      list: Rc<RefCell<Vec<Value>>>,
+      index: usize
+   },
+   Tuple {
+      tuple: Rc<[Value]>,
+      index: usize
+   },
+   Chars {
+      str: Rc<str>,
+      offset: usize
+   },
+   Range {
+      next: i64,
+      remaining: i64,
+      step: i64
+   },
+   /// Stops after the first error instead of retrying the read forever.
+   FileLines {
+      file: Rc<RefCell<FileHandle>>,
+      failed: bool
+   },
+   /// A iterator value, advancing this advances the value too.
+   Shared(Rc<RefCell<NativeIterator>>),
+   /// A struct with a next method.
+   Struct {
+      instance: Value,
+      next: usize
+   },
+   Map {
+      iterator: Box<NativeIterator>,
+      function: Value
+   },
+   Filter {
+      iterator: Box<NativeIterator>,
+      function: Value
+   },
+   Zip {
+      left: Box<NativeIterator>,
+      right: Box<NativeIterator>
+   },
+   Enumerate {
+      iterator: Box<NativeIterator>,
+      index: i64
+   },
+   /// first is None after it has been exhausted.
+   Chain {
+      first: Option<Box<NativeIterator>>,
+      second: Box<NativeIterator>
+   },
+   Take {
+      iterator: Box<NativeIterator>,
+      remaining: i64
+   },
+   Skip {
+      iterator: Box<NativeIterator>,
+      remaining: i64
+   },
+   StepBy {
+      iterator: Box<NativeIterator>,
+      step: i64,
+      first: bool
+   },
+   TakeWhile {
+      iterator: Box<NativeIterator>,
+      function: Value,
+      done: bool
+   },
+   FlatMap {
+      iterator: Box<NativeIterator>,
+      function: Value,
+      current: Option<Box<NativeIterator>>
+   },
+   /// Returned by calling a function which contains a yield statement.
+   Generator(Box<Frame>)
+}
+
+impl NativeIterator {
+   /// Returns a iterator over a iterable value, or a err if it is not
+   /// iterable.
+   pub fn from_value(data: &Data, value: Value) -> Result<NativeIterator, Value> {
+      Ok(match value {
+         Value::List(list) => NativeIterator::List { list, index: 0 },
+         Value::Tuple(tuple) => NativeIterator::Tuple { tuple, index: 0 },
+         Value::Str(str) => NativeIterator::Chars { str, offset: 0 },
+         Value::Range { start, stop, step } =>
+            NativeIterator::Range { next: start, remaining: range_len(start, stop, step), step },
+         Value::File(file) => NativeIterator::FileLines { file, failed: false },
+         Value::Iterator(iterator) => NativeIterator::Shared(iterator),
+         Value::Struct(..) => match value.get_field(data, data.reserved_idents.next) {
+            Value::Method { function_id, .. } => NativeIterator::Struct { instance: value, next: function_id },
+            _ => return Err(Value::new_err("NotIterable"))
+         },
+         _ => return Err(Value::new_err("NotIterable"))
+      })
+   }
+
+   /// Advance a iterator which may be shared. Returns a err instead of
+   /// panicking when the iterator is re-entered, like a generator which
+   /// advances itself.
+   pub fn next_shared(iterator: &RefCell<NativeIterator>, data: &Data, state: &mut State) -> Value {
+      match iterator.try_borrow_mut() {
+         Ok(mut iterator) => iterator.next(data, state),
+         Err(_) => Value::new_err("Iterator is already running.")
+      }
+   }
+
+   pub fn next(&mut self, data: &Data, state: &mut State) -> Value {
+      match self {
+         NativeIterator::StdinLines { failed } => {
+            if *failed {
+               return Value::IterEnd;
+            }
+            let line = Value::read_line();
+            *failed = matches!(line, Value::Err(..));
+            line
+         },
+         NativeIterator::List { list, index } => {
+            let Some(item) = list.borrow().get(*index).cloned() else { return Value::IterEnd };
+            *index += 1;
+            item
+         },
+         NativeIterator::Tuple { tuple, index } => {
+            let Some(item) = tuple.get(*index).cloned() else { return Value::IterEnd };
+            *index += 1;
+            item
+         },
+         NativeIterator::Chars { str, offset } => {
+            let Some(char) = str[*offset..].chars().next() else { return Value::IterEnd };
+            *offset += char.len_utf8();
+            char.to_string().into()
+         },
+         NativeIterator::Range { next, remaining, step } => {
+            if *remaining == 0 {
+               return Value::IterEnd;
+            }
+            let value = *next;
+            *next = next.wrapping_add(*step);
+            *remaining -= 1;
+            value.into()
+         },
+         NativeIterator::FileLines { file, failed } => {
+            if *failed {
+               return Value::IterEnd;
+            }
+            let line = Value::File(file.clone()).read_file_line();
+            *failed = matches!(line, Value::Err(..));
+            line
+         },
+         NativeIterator::Shared(iterator) => NativeIterator::next_shared(iterator, data, state),
+         NativeIterator::Struct { instance, next } => call_function(data, state, *next, vec![instance.clone()]),
+         NativeIterator::Map { iterator, function } => match iterator.next(data, state) {
+            Value::IterEnd => Value::IterEnd,
+            item => call_value(data, state, function, vec![item])
+         },
+         NativeIterator::Filter { iterator, function } => loop {
+            let item = iterator.next(data, state);
+            if let Value::IterEnd = item {
+               break item;
+            }
+            if call_value(data, state, function, vec![item.clone()]).is_truthy() {
+               break item;
+            }
+         },
+         NativeIterator::Zip { left, right } => {
+            let left = left.next(data, state);
+            if let Value::IterEnd = left {
+               return left;
+            }
+            let right = right.next(data, state);
+            if let Value::IterEnd = right {
+               return right;
+            }
+            Value::List(Rc::new(RefCell::new(vec![left, right])))
+         },
+         NativeIterator::Enumerate { iterator, index } => {
+            let item = iterator.next(data, state);
+            if let Value::IterEnd = item {
+               return item;
+            }
+            *index += 1;
+            Value::List(Rc::new(RefCell::new(vec![(*index - 1).into(), item])))
+         },
+         NativeIterator::Chain { first, second } => {
+            if let Some(iterator) = first {
+               let item = iterator.next(data, state);
+               if !matches!(item, Value::IterEnd) {
+                  return item;
+               }
+               *first = None;
+            }
+            second.next(data, state)
+         },
+         NativeIterator::Take { iterator, remaining } => {
+            if *remaining <= 0 {
+               return Value::IterEnd;
+            }
+            *remaining -= 1;
+            iterator.next(data, state)
+         },
+         NativeIterator::Skip { iterator, remaining } => {
+            while 0 < *remaining {
+               *remaining -= 1;
+               if let Value::IterEnd = iterator.next(data, state) {
+                  return Value::IterEnd;
+               }
+            }
+            iterator.next(data, state)
+         },
+         NativeIterator::StepBy { iterator, step, first } => {
+            if *first {
+               *first = false;
+               return iterator.next(data, state);
+            }
+            for _ in 1..*step {
+               if let Value::IterEnd = iterator.next(data, state) {
+                  return Value::IterEnd;
+               }
+            }
+            iterator.next(data, state)
+         },
+         NativeIterator::TakeWhile { iterator, function, done } => {
+            if *done {
+               return Value::IterEnd;
+            }
+            let item = iterator.next(data, state);
+            if let Value::IterEnd = item {
+               return item;
+            }
+            if call_value(data, state, function, vec![item.clone()]).is_truthy() {
+               item
+            } else {
+               *done = true;
+               Value::IterEnd
+            }
+         },
+         NativeIterator::FlatMap { iterator, function, current } => loop {
+            if let Some(current) = current {
+               let item = current.next(data, state);
+               if !matches!(item, Value::IterEnd) {
+                  break item;
+               }
+            }
+            let item = iterator.next(data, state);
+            if let Value::IterEnd = item {
+               break item;
+            }
+            match NativeIterator::from_value(data, call_value(data, state, function, vec![item])) {
+               Ok(iterator) => *current = Some(Box::new(iterator)),
+               Err(err) => {
+                  *current = None;
+                  break err;
+               }
+            }
+         },
+         NativeIterator::Generator(frame) => frame.resume(data, state)
+      }
+   }
+
+   /// Returns the remaining items.
+   pub fn collect(&mut self, data: &Data, state: &mut State) -> Vec<Value> {
+      let mut items = vec![];
+      loop {
+         match self.next(data, state) {
+            Value::IterEnd => break items,
+            item => items.push(item)
+         }
+      }
+   }
+}
+
+impl From<NativeIterator> for Value {
+   fn from(iterator: NativeIterator) -> Self {
+      Value::Iterator(Rc::new(RefCell::new(iterator)))
+   }
+}
+
+/// Evaluate exactly N parameters.
+fn fixed_arguments<const N: usize>(data: &Data, state: &mut State, parameters: &[Expr], name: &str) -> [Value; N] {
+   arguments(data, state, parameters, N, N, name).try_into().unwrap()
+}
+
+/// Returns a iterator over iterable, or a err if it is not iterable.
+macro_rules! iterator {
+   ($data:expr, $iterable:expr) => {
+      match NativeIterator::from_value($data, $iterable) {
+         Ok(iterator) => Box::new(iterator),
+         Err(err) => return err
+      }
+   };
+}
+
+/// Returns count if it is a int which is atleast min, or a err otherwise.
+macro_rules! count {
+   ($count:expr, $min:expr) => {
+      match $count {
+         Value::Int(count) if $min <= count => count,
+         _ => return Value::new_err("TypeError")
+      }
+   };
+}
+
+pub fn map(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [iterable, function] = fixed_arguments(data, state, parameters, "map");
+   NativeIterator::Map { iterator: iterator!(data, iterable), function }.into()
+}
+
+pub fn filter(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [iterable, function] = fixed_arguments(data, state, parameters, "filter");
+   NativeIterator::Filter { iterator: iterator!(data, iterable), function }.into()
+}
+
+pub fn zip(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [left, right] = fixed_arguments(data, state, parameters, "zip");
+   NativeIterator::Zip { left: iterator!(data, left), right: iterator!(data, right) }.into()
+}
+
+pub fn enumerate(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [iterable] = fixed_arguments(data, state, parameters, "enumerate");
+   NativeIterator::Enumerate { iterator: iterator!(data, iterable), index: 0 }.into()
+}
+
+pub fn chain(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [first, second] = fixed_arguments(data, state, parameters, "chain");
+   NativeIterator::Chain { first: Some(iterator!(data, first)), second: iterator!(data, second) }.into()
+}
+
+pub fn take(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [iterable, count] = fixed_arguments(data, state, parameters, "take");
+   NativeIterator::Take { remaining: count!(count, 0), iterator: iterator!(data, iterable) }.into()
+}
+
+pub fn skip(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [iterable, count] = fixed_arguments(data, state, parameters, "skip");
+   NativeIterator::Skip { remaining: count!(count, 0), iterator: iterator!(data, iterable) }.into()
+}
+
+pub fn step_by(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [iterable, step] = fixed_arguments(data, state, parameters, "step_by");
+   NativeIterator::StepBy { step: count!(step, 1), iterator: iterator!(data, iterable), first: true }.into()
+}
+
+pub fn take_while(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [iterable, function] = fixed_arguments(data, state, parameters, "take_while");
+   NativeIterator::TakeWhile { iterator: iterator!(data, iterable), function, done: false }.into()
+}
+
+pub fn flat_map(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [iterable, function] = fixed_arguments(data, state, parameters, "flat_map");
+   NativeIterator::FlatMap { iterator: iterator!(data, iterable), function, current: None }.into()
+}
+
+pub fn collect(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [iterable] = fixed_arguments(data, state, parameters, "collect");
+   let mut iterator = iterator!(data, iterable);
+   Value::List(Rc::new(RefCell::new(iterator.collect(data, state))))
+}
+
+/// Consumes the iterable and returns the number of items.
+pub fn count(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [iterable] = fixed_arguments(data, state, parameters, "count");
+   let mut iterator = iterator!(data, iterable);
+   let mut count: i64 = 0;
+   loop {
+      match iterator.next(data, state) {
+         Value::IterEnd => break count.into(),
+         _ => count += 1
+      }
+   }
+}
+
+/// sum(iterable) or sum(iterable, start), start defaults to 0.
+pub fn sum(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let mut arguments = arguments(data, state, parameters, 1, 2, "sum").into_iter();
+   let mut iterator = iterator!(data, arguments.next().unwrap());
+   let mut sum = arguments.next().unwrap_or(Value::Int(0));
+   loop {
+      match iterator.next(data, state) {
+         Value::IterEnd => break sum,
+         item => sum = sum.add(item)
+      }
+   }
+}
+
+/// Returns true if function returns a truthy value for any item, or if any item
+/// is truthy when function is not given.
+pub fn any(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let mut arguments = arguments(data, state, parameters, 1, 2, "any").into_iter();
+   let mut iterator = iterator!(data, arguments.next().unwrap());
+   let function = arguments.next();
+   loop {
+      let item = match iterator.next(data, state) {
+         Value::IterEnd => break false.into(),
+         item => item
+      };
+      let item = match &function {
+         Some(function) => call_value(data, state, function, vec![item]),
+         None => item
+      };
+      if item.is_truthy() {
+         break true.into();
+      }
+   }
+}
+
+/// Returns true if function returns a truthy value for all items, or if all
+/// items are truthy when function is not given.
+pub fn all(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let mut arguments = arguments(data, state, parameters, 1, 2, "all").into_iter();
+   let mut iterator = iterator!(data, arguments.next().unwrap());
+   let function = arguments.next();
+   loop {
+      let item = match iterator.next(data, state) {
+         Value::IterEnd => break true.into(),
+         item => item
+      };
+      let item = match &function {
+         Some(function) => call_value(data, state, function, vec![item]),
+         None => item
+      };
+      if !item.is_truthy() {
+         break false.into();
+      }
+   }
+}
+
+/// reduce(iterable, function) or reduce(iterable, function, initial). Without
+/// initial the first item is used, and nil is returned if there are no items.
+pub fn reduce(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let mut arguments = arguments(data, state, parameters, 2, 3, "reduce").into_iter();
+   let mut iterator = iterator!(data, arguments.next().unwrap());
+   let function = arguments.next().unwrap();
+   let mut acc = match arguments.next() {
+      Some(initial) => initial,
+      None => match iterator.next(data, state) {
+         Value::IterEnd => return Value::Nil,
+         item => item
+      }
+   };
+   loop {
+      match iterator.next(data, state) {
+         Value::IterEnd => break acc,
+         item => acc = call_value(data, state, &function, vec![acc, item])
+      }
+   }
+}
@@ -1,6 +1,9 @@
+mod file;
 mod ir;
+mod iter;
 mod misc;
 mod plugins;
+mod reflect;
 mod state;
 mod value;
 use std::{env, fs::File, io::BufReader};
@@ -14,5 +17,5 @@ fn main() {
    let reader = BufReader::new(file);
    let data: Data = serde_json::from_reader(reader).unwrap();
    let mut state: State = State::new();
-   call_by_name(&data, &mut state, "main", &vec![]);
+   call_by_name(&data, &mut state, "main", &[]);
 }
@@ -1,19 +1,213 @@
+use std::cmp::Ordering;
+
 /// Calculate the remainder of left divided by right, ensuring the result has
 /// the same sign as right.
 pub fn modulo(left: i64, right: i64) -> i64 {
-   let mut result = left % right;
-   if (result < 0) != (right < 0) {
+   let mut result = left.wrapping_rem(right);
+   if result != 0 && (result < 0) != (right < 0) {
       result += right;
    }
    result
 }
 
+/// Divide left by right rounding towards negative infinity, returns None on
+/// overflow. right must not be 0.
+pub fn checked_floor_div(left: i64, right: i64) -> Option<i64> {
+   let quotient = left.checked_div(right)?;
+   Some(if left % right != 0 && (left < 0) != (right < 0) { quotient - 1 } else { quotient })
+}
+
+/// Raise base to a non-negative exponent, returns None on overflow.
+pub fn checked_pow(base: i64, exponent: i64) -> Option<i64> {
+   match (base, u32::try_from(exponent)) {
+      (_, Ok(exponent)) => base.checked_pow(exponent),
+      (0 | 1, Err(_)) => Some(base),
+      (-1, Err(_)) => Some(if exponent % 2 == 0 { 1 } else { -1 }),
+      _ => None
+   }
+}
+
 /// Calculate the remainder of left divided by right, ensuring the result has
 /// the same sign as right.
 pub fn fmodulo(left: f64, right: f64) -> f64 {
    let mut result = left % right;
-   if (result < 0.) != (right < 0.) {
+   if result != 0. && (result < 0.) != (right < 0.) {
       result += right;
    }
    result
 }
+
+/// Remove a trailing "\n" or "\r\n".
+pub fn trim_newline(line: &mut String) {
+   if line.ends_with('\n') {
+      line.pop();
+      if line.ends_with('\r') {
+         line.pop();
+      }
+   }
+}
+
+/// Resolve a index into a sequence of length len, negative indices count from
+/// the end. Returns None if the index is out of bounds.
+pub fn resolve_index(index: i64, len: usize) -> Option<usize> {
+   let index = if index < 0 { index as i128 + len as i128 } else { index as i128 };
+   (0 <= index && index < len as i128).then_some(index as usize)
+}
+
+/// Clamp a index into 0..=len, negative indices count from the end.
+pub fn clamp_index(index: i64, len: usize) -> usize {
+   let index = if index < 0 { index as i128 + len as i128 } else { index as i128 };
+   index.clamp(0, len as i128) as usize
+}
+
+/// Indices selected by slicing a sequence of length len from start up to stop
+/// by step, like Python. Out of range bounds are clamped, step must not be 0.
+pub fn slice_indices(len: usize, start: Option<i64>, stop: Option<i64>, step: i64) -> Vec<usize> {
+   let len = len as i128;
+   let step = step as i128;
+   // Negative bounds count from the end, then bounds are clamped to
+   // 0..=len for a positive step or -1..len for a negative step.
+   let (min, max) = if 0 < step { (0, len) } else { (-1, len - 1) };
+   let bound = |index: Option<i64>, default| match index {
+      None => default,
+      Some(index) if index < 0 => (index as i128 + len).clamp(min, max),
+      Some(index) => (index as i128).clamp(min, max)
+   };
+   let (mut index, stop) =
+      if 0 < step { (bound(start, 0), bound(stop, len)) } else { (bound(start, len - 1), bound(stop, -1)) };
+   let mut indices = vec![];
+   while if 0 < step { index < stop } else { index > stop } {
+      indices.push(index as usize);
+      index += step;
+   }
+   indices
+}
+
+/// Number of values in the range start..stop with step, step must not be 0.
+pub fn range_len(start: i64, stop: i64, step: i64) -> i64 {
+   let (start, stop, step) = (start as i128, stop as i128, step as i128);
+   let len = if 0 < step { (stop - start + step - 1) / step } else { (start - stop - step - 1) / -step };
+   len.clamp(0, i64::MAX as i128) as i64
+}
+
+/// Stable merge sort of the indices 0..len. Unlike slice::sort_by, this does
+/// not panic if compare is not a total order.
+pub fn sort_indices(len: usize, mut compare: impl FnMut(usize, usize) -> Ordering) -> Vec<usize> {
+   let mut src: Vec<usize> = (0..len).collect();
+   let mut dst = vec![0; len];
+   let mut width = 1;
+   while width < len {
+      for start in (0..len).step_by(2 * width) {
+         let mid = (start + width).min(len);
+         let end = (start + 2 * width).min(len);
+         let (mut i, mut j) = (start, mid);
+         for item in &mut dst[start..end] {
+            // Take from the left run unless the right item is less, to keep
+            // equal items in their original order.
+            if i < mid && (j == end || compare(src[j], src[i]) != Ordering::Less) {
+               *item = src[i];
+               i += 1;
+            } else {
+               *item = src[j];
+               j += 1;
+            }
+         }
+      }
+      (src, dst) = (dst, src);
+      width *= 2;
+   }
+   src
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+
+   #[test]
+   fn slice_indices_steps() {
+      assert_eq!(slice_indices(5, None, None, 1), [0, 1, 2, 3, 4]);
+      assert_eq!(slice_indices(5, None, None, -1), [4, 3, 2, 1, 0]);
+      assert_eq!(slice_indices(5, Some(-2), None, 1), [3, 4]);
+      assert_eq!(slice_indices(5, Some(10), Some(-10), -2), [4, 2, 0]);
+      assert_eq!(slice_indices(5, None, None, i64::MAX), [0]);
+      assert_eq!(slice_indices(5, None, None, i64::MIN), [4]);
+   }
+
+   #[test]
+   fn slice_indices_bounds() {
+      assert_eq!(slice_indices(3, Some(i64::MIN), Some(i64::MAX), 1), [0, 1, 2]);
+      assert_eq!(slice_indices(3, Some(i64::MAX), Some(i64::MIN), -1), [2, 1, 0]);
+      assert_eq!(slice_indices(3, Some(-i64::MAX), None, 2), [0, 2]);
+   }
+
+   #[test]
+   fn slice_indices_empty() {
+      assert!(slice_indices(5, Some(3), Some(1), 1).is_empty());
+      assert!(slice_indices(5, Some(1), Some(3), -1).is_empty());
+      assert!(slice_indices(0, None, None, 1).is_empty());
+      assert!(slice_indices(0, None, None, -1).is_empty());
+   }
+
+   #[test]
+   fn range_len_steps() {
+      assert_eq!(range_len(0, 10, 3), 4);
+      assert_eq!(range_len(10, 0, -3), 4);
+      assert_eq!(range_len(0, 1, i64::MIN), 0);
+      assert_eq!(range_len(i64::MAX, i64::MIN, i64::MIN), 2);
+      assert_eq!(range_len(i64::MIN, i64::MAX, i64::MAX), 3);
+   }
+
+   #[test]
+   fn range_len_bounds() {
+      assert_eq!(range_len(i64::MIN, i64::MAX, 1), i64::MAX);
+      assert_eq!(range_len(i64::MAX, i64::MIN, -1), i64::MAX);
+      assert_eq!(range_len(-i64::MAX, i64::MAX, 2), i64::MAX);
+   }
+
+   #[test]
+   fn range_len_empty() {
+      assert_eq!(range_len(0, 0, 1), 0);
+      assert_eq!(range_len(5, 0, 1), 0);
+      assert_eq!(range_len(0, 5, -1), 0);
+      assert_eq!(range_len(i64::MAX, i64::MIN, 1), 0);
+   }
+
+   #[test]
+   fn clamp_index_bounds() {
+      assert_eq!(clamp_index(2, 5), 2);
+      assert_eq!(clamp_index(-1, 5), 4);
+      assert_eq!(clamp_index(-10, 5), 0);
+      assert_eq!(clamp_index(i64::MIN, 5), 0);
+      assert_eq!(clamp_index(-i64::MAX, 5), 0);
+      assert_eq!(clamp_index(i64::MAX, 5), 5);
+      assert_eq!(clamp_index(i64::MIN, 0), 0);
+   }
+
+   #[test]
+   fn checked_floor_div_rounding() {
+      assert_eq!(checked_floor_div(7, 2), Some(3));
+      assert_eq!(checked_floor_div(-7, 2), Some(-4));
+      assert_eq!(checked_floor_div(7, -2), Some(-4));
+      assert_eq!(checked_floor_div(-7, -2), Some(3));
+      assert_eq!(checked_floor_div(6, -3), Some(-2));
+   }
+
+   #[test]
+   fn checked_floor_div_bounds() {
+      assert_eq!(checked_floor_div(i64::MIN, -1), None);
+      assert_eq!(checked_floor_div(i64::MIN, 1), Some(i64::MIN));
+      assert_eq!(checked_floor_div(i64::MAX, -1), Some(-i64::MAX));
+      assert_eq!(checked_floor_div(i64::MAX, i64::MIN), Some(-1));
+      assert_eq!(checked_floor_div(i64::MIN, i64::MAX), Some(-2));
+   }
+
+   #[test]
+   fn fmodulo_signs() {
+      assert_eq!(fmodulo(7., 3.), 1.);
+      assert_eq!(fmodulo(-7., 3.), 2.);
+      assert_eq!(fmodulo(7., -3.), -2.);
+      assert_eq!(fmodulo(-7., -3.), -1.);
+      assert_eq!(fmodulo(6., -3.), 0.);
+      assert!(fmodulo(1., 0.).is_nan());
+   }
+}
@@ -1,22 +1,369 @@
-use std::cell::RefCell;
+use std::{
+   cell::RefCell,
+   cmp::Ordering,
+   fs,
+   path::{self, Component, Path, PathBuf},
+   time::UNIX_EPOCH
+};
 
 use crate::{
    ir::{Data, Expr},
-   state::State,
+   iter::*,
+   misc::sort_indices,
+   reflect::*,
+   state::{call_value, State},
    value::Value
 };
 
-pub fn plugin_call(data: &Data, state: &mut State, id: usize, parameters: &Vec<Expr>) -> Value {
+pub fn plugin_call(data: &Data, state: &mut State, id: usize, parameters: &[Expr]) -> Value {
    PLUGINS[id](data, state, parameters)
 }
 
-static PLUGINS: &[fn(&Data, &mut State, &Vec<Expr>) -> Value] = &[split];
+type PluginFn = fn(&Data, &mut State, &[Expr]) -> Value;
+
+static PLUGINS: &[PluginFn] = &[
+   split,
+   exists,
+   is_dir,
+   list_dir,
+   mkdir,
+   remove,
+   rename,
+   copy,
+   stat,
+   glob,
+   path_join,
+   basename,
+   dirname,
+   extension,
+   with_extension,
+   normalize,
+   absolute,
+   map,
+   filter,
+   zip,
+   enumerate,
+   chain,
+   take,
+   skip,
+   step_by,
+   take_while,
+   flat_map,
+   collect,
+   sum,
+   any,
+   all,
+   reduce,
+   sort,
+   sorted,
+   count,
+   fields,
+   methods,
+   getattr,
+   setattr,
+   hasattr,
+   prototype,
+   new,
+   name_of,
+   params_of,
+   arity,
+   self_of,
+   bind,
+   partial
+];
+
+/// Evaluate parameters, dies if there are less than min or more than max.
+pub fn arguments(data: &Data, state: &mut State, parameters: &[Expr], min: usize, max: usize, name: &str) -> Vec<Value> {
+   if parameters.len() < min || max < parameters.len() {
+      state.die(data, Value::new_err(&format!("Wrong number of arguments to {name}().")), None)
+   }
+   parameters.iter().map(|v| v.eval(data, state)).collect()
+}
 
-fn split(data: &Data, state: &mut State, parameters: &Vec<Expr>) -> Value {
+fn split(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
    if parameters.len() != 2 {
-      state.die(data, Value::new_err("Wrong number of arguments to load_json()."), None)
+      state.die(data, Value::new_err("Wrong number of arguments to split()."), None)
    }
    let Value::Str(string) = parameters[0].eval(data, state) else { return Value::Nil };
    let Value::Str(separator) = parameters[1].eval(data, state) else { return Value::Nil };
    Value::List(RefCell::new(string.split(&*separator).map(|v| v.into()).collect()).into())
 }
+
+/// Sort items in a stable order. key is called on each item to get the value
+/// to compare, and compare is called with two keys and must return a number
+/// which is negative, zero or positive. When compare is not given the keys are
+/// compared like the < operator. Returns a err if two keys cannot be compared.
+fn sort_values(
+   data: &Data,
+   state: &mut State,
+   items: Vec<Value>,
+   key: &Value,
+   reverse: bool,
+   compare: &Value
+) -> Result<Vec<Value>, Value> {
+   let keys = match key {
+      Value::Nil => items.clone(),
+      key => items.iter().map(|item| call_value(data, state, key, vec![item.clone()])).collect()
+   };
+   let mut error = None;
+   let indices = sort_indices(items.len(), |left, right| {
+      let (left, right) = if reverse { (right, left) } else { (left, right) };
+      let ordering = match compare {
+         Value::Nil => keys[left].compare(&keys[right]),
+         compare => call_value(data, state, compare, vec![keys[left].clone(), keys[right].clone()]).compare(&Value::Int(0))
+      };
+      ordering.unwrap_or_else(|| {
+         error.get_or_insert_with(|| Value::new_err("TypeError"));
+         Ordering::Equal
+      })
+   });
+   if let Some(error) = error {
+      return Err(error);
+   }
+   Ok(indices.into_iter().map(|index| items[index].clone()).collect())
+}
+
+/// Returns the optional key, reverse and compare arguments of sort and sorted.
+fn sort_options(arguments: &[Value]) -> (&Value, bool, &Value) {
+   (
+      arguments.first().unwrap_or(&Value::Nil),
+      arguments.get(1).is_some_and(Value::is_truthy),
+      arguments.get(2).unwrap_or(&Value::Nil)
+   )
+}
+
+/// sort(list, key, reverse, compare) sorts the list in place, all but the
+/// first argument are optional. The list is not changed on error.
+fn sort(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let arguments = arguments(data, state, parameters, 1, 4, "sort");
+   let Value::List(list) = &arguments[0] else { return Value::new_err("TypeError") };
+   let (key, reverse, compare) = sort_options(&arguments[1..]);
+   let items = list.borrow().clone();
+   match sort_values(data, state, items, key, reverse, compare) {
+      Err(err) => err,
+      Ok(items) => {
+         *list.borrow_mut() = items;
+         Value::Nil
+      }
+   }
+}
+
+/// sorted(iterable, key, reverse, compare) returns a new sorted list, all but
+/// the first argument are optional.
+fn sorted(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let arguments = arguments(data, state, parameters, 1, 4, "sorted");
+   let items = match NativeIterator::from_value(data, arguments[0].clone()) {
+      Ok(mut iterator) => iterator.collect(data, state),
+      Err(err) => return err
+   };
+   let (key, reverse, compare) = sort_options(&arguments[1..]);
+   match sort_values(data, state, items, key, reverse, compare) {
+      Err(err) => err,
+      Ok(items) => Value::List(RefCell::new(items).into())
+   }
+}
+
+fn exists(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(path)] = &arguments(data, state, parameters, 1, 1, "exists")[..] else {
+      return Value::new_err("TypeError");
+   };
+   Value::from_io(fs::exists(&**path))
+}
+
+fn is_dir(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(path)] = &arguments(data, state, parameters, 1, 1, "is_dir")[..] else {
+      return Value::new_err("TypeError");
+   };
+   fs::metadata(&**path).map(|metadata| metadata.is_dir()).unwrap_or(false).into()
+}
+
+/// Returns the sorted names of the entries in a directory.
+fn list_dir(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(path)] = &arguments(data, state, parameters, 1, 1, "list_dir")[..] else {
+      return Value::new_err("TypeError");
+   };
+   let names = fs::read_dir(&**path).and_then(|entries| {
+      let mut names = entries
+         .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+         .collect::<Result<Vec<_>, _>>()?;
+      names.sort();
+      Ok(names)
+   });
+   match names {
+      Err(err) => Value::from_error(err),
+      Ok(names) => Value::List(RefCell::new(names.into_iter().map(Value::from).collect()).into())
+   }
+}
+
+/// Creates a directory, also creates missing parents if recursive is true.
+fn mkdir(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let (path, recursive) = match &arguments(data, state, parameters, 1, 2, "mkdir")[..] {
+      [Value::Str(path)] => (path.clone(), false),
+      [Value::Str(path), recursive] => (path.clone(), recursive.is_truthy()),
+      _ => return Value::new_err("TypeError")
+   };
+   let result = if recursive { fs::create_dir_all(&*path) } else { fs::create_dir(&*path) };
+   Value::from_io(result.map(|_| true))
+}
+
+/// Removes a file or an empty directory.
+fn remove(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(path)] = &arguments(data, state, parameters, 1, 1, "remove")[..] else {
+      return Value::new_err("TypeError");
+   };
+   let result = match fs::symlink_metadata(&**path) {
+      Ok(metadata) if metadata.is_dir() => fs::remove_dir(&**path),
+      _ => fs::remove_file(&**path)
+   };
+   Value::from_io(result.map(|_| true))
+}
+
+fn rename(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(from), Value::Str(to)] = &arguments(data, state, parameters, 2, 2, "rename")[..] else {
+      return Value::new_err("TypeError");
+   };
+   Value::from_io(fs::rename(&**from, &**to).map(|_| true))
+}
+
+/// Copies a file, returns the number of bytes copied.
+fn copy(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(from), Value::Str(to)] = &arguments(data, state, parameters, 2, 2, "copy")[..] else {
+      return Value::new_err("TypeError");
+   };
+   Value::from_io(fs::copy(&**from, &**to).map(|size| size as usize))
+}
+
+/// Returns [size, mtime, permissions], where mtime is seconds since the unix
+/// epoch as a float and permissions are the unix mode bits.
+fn stat(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(path)] = &arguments(data, state, parameters, 1, 1, "stat")[..] else {
+      return Value::new_err("TypeError");
+   };
+   let metadata = match fs::metadata(&**path) {
+      Err(err) => return Value::from_error(err),
+      Ok(metadata) => metadata
+   };
+   let mtime = match metadata.modified() {
+      Err(err) => return Value::from_error(err),
+      Ok(mtime) => match mtime.duration_since(UNIX_EPOCH) {
+         Ok(duration) => duration.as_secs_f64(),
+         Err(err) => -err.duration().as_secs_f64()
+      }
+   };
+   #[cfg(unix)]
+   let permissions = std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) as i64;
+   #[cfg(not(unix))]
+   let permissions = if metadata.permissions().readonly() { 0o444 } else { 0o666 };
+   Value::List(RefCell::new(vec![(metadata.len() as i64).into(), mtime.into(), permissions.into()]).into())
+}
+
+/// Returns the sorted paths matching a shell-style pattern.
+fn glob(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(pattern)] = &arguments(data, state, parameters, 1, 1, "glob")[..] else {
+      return Value::new_err("TypeError");
+   };
+   let paths = match glob::glob(pattern) {
+      Err(err) => return Value::from_error(err),
+      Ok(paths) => paths
+   };
+   let mut list = vec![];
+   for path in paths {
+      match path {
+         Err(err) => return Value::from_error(err),
+         Ok(path) => match path.to_str() {
+            Some(path) => list.push(path.into()),
+            None => return Value::new_err("Path is not valid UTF-8.")
+         }
+      }
+   }
+   Value::List(RefCell::new(list).into())
+}
+
+/// Converts a path to a str, returns a err if it is not valid UTF-8.
+fn path_value(path: &Path) -> Value {
+   match path.to_str() {
+      Some(path) => path.into(),
+      None => Value::new_err("Path is not valid UTF-8.")
+   }
+}
+
+/// Joins paths, an absolute path replaces everything before it.
+fn path_join(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let mut path = PathBuf::new();
+   for part in arguments(data, state, parameters, 1, usize::MAX, "path_join") {
+      let Value::Str(part) = part else { return Value::new_err("TypeError") };
+      path.push(&*part);
+   }
+   path_value(&path)
+}
+
+/// Returns the last component of path, or "" if there is none.
+fn basename(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(path)] = &arguments(data, state, parameters, 1, 1, "basename")[..] else {
+      return Value::new_err("TypeError");
+   };
+   path_value(Path::new(&**path).file_name().map(Path::new).unwrap_or(Path::new("")))
+}
+
+/// Returns path without its last component. The root is its own dirname.
+fn dirname(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(path)] = &arguments(data, state, parameters, 1, 1, "dirname")[..] else {
+      return Value::new_err("TypeError");
+   };
+   let path = Path::new(&**path);
+   path_value(path.parent().unwrap_or(path))
+}
+
+/// Returns the extension of the last component without the dot, or "".
+fn extension(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(path)] = &arguments(data, state, parameters, 1, 1, "extension")[..] else {
+      return Value::new_err("TypeError");
+   };
+   path_value(Path::new(&**path).extension().map(Path::new).unwrap_or(Path::new("")))
+}
+
+/// Replaces the extension of path, an empty extension removes it.
+fn with_extension(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(path), Value::Str(extension)] = &arguments(data, state, parameters, 2, 2, "with_extension")[..] else {
+      return Value::new_err("TypeError");
+   };
+   path_value(&Path::new(&**path).with_extension(&**extension))
+}
+
+/// Lexically removes "." components, redundant separators and ".." after a
+/// normal component. Does not access the filesystem.
+fn normalize(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(path)] = &arguments(data, state, parameters, 1, 1, "normalize")[..] else {
+      return Value::new_err("TypeError");
+   };
+   let mut components: Vec<Component> = vec![];
+   for component in Path::new(&**path).components() {
+      match component {
+         Component::CurDir => {},
+         Component::ParentDir => match components.last() {
+            Some(Component::Normal(..)) => {
+               components.pop();
+            },
+            Some(Component::RootDir | Component::Prefix(..)) => {},
+            _ => components.push(component)
+         },
+         _ => components.push(component)
+      }
+   }
+   if components.is_empty() {
+      return ".".into();
+   }
+   path_value(&components.iter().collect::<PathBuf>())
+}
+
+/// Makes path absolute by joining it to the current directory, does not
+/// resolve symlinks.
+fn absolute(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let [Value::Str(path)] = &arguments(data, state, parameters, 1, 1, "absolute")[..] else {
+      return Value::new_err("TypeError");
+   };
+   match path::absolute(&**path) {
+      Err(err) => Value::from_error(err),
+      Ok(path) => path_value(&path)
+   }
+}
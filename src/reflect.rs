@@ -0,0 +1,215 @@
+use std::{collections::BTreeMap, rc::Rc};
+
+use crate::{
+   ir::{Data, Expr},
+   iter::NativeIterator,
+   plugins::arguments,
+   state::{construct, get_field, resolve_callable, State},
+   value::{Struct, Value}
+};
+
+/// Returns the prototype and variant of a instance or prototype value.
+fn prototype_of(value: &Value) -> Option<(usize, Option<usize>)> {
+   match value {
+      Value::Struct(instance) => {
+         let instance = instance.borrow();
+         Some((instance.prototype, instance.variant))
+      },
+      &Value::Prototype { prototype, variant } => Some((prototype, variant)),
+      _ => None
+   }
+}
+
+fn field_map(data: &Data, prototype: usize, variant: Option<usize>) -> &BTreeMap<usize, usize> {
+   Struct { prototype, variant, values: vec![] }.field_map(data)
+}
+
+fn ident_id(data: &Data, name: &Value) -> Option<usize> {
+   let Value::Str(name) = name else { return None };
+   data.ident_map.iter().find(|(_, ident)| **ident == **name).map(|(&id, _)| id)
+}
+
+/// Returns the names of the fields of a instance or prototype, in the order of
+/// the values of the instance.
+pub fn fields(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let arguments = arguments(data, state, parameters, 1, 1, "fields");
+   let Some((prototype, variant)) = prototype_of(&arguments[0]) else { return Value::Nil };
+   let mut fields: Vec<(usize, usize)> = field_map(data, prototype, variant).iter().map(|(&id, &index)| (index, id)).collect();
+   fields.sort();
+   fields.into_iter().map(|(_, id)| data.ident_map[&id].as_str().into()).collect::<Vec<Value>>().into()
+}
+
+/// Returns the names of the methods of a instance or prototype, including the
+/// methods inherited from its parents.
+pub fn methods(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let arguments = arguments(data, state, parameters, 1, 1, "methods");
+   let Some((mut prototype, _)) = prototype_of(&arguments[0]) else { return Value::Nil };
+   let mut methods: Vec<usize> = vec![];
+   loop {
+      let definition = &data.prototypes[prototype];
+      methods.extend(definition.method_map.keys().filter(|id| !methods.contains(id)).collect::<Vec<_>>());
+      let Some(parent) = definition.parent else { break };
+      prototype = parent;
+   }
+   methods.into_iter().map(|id| data.ident_map[&id].as_str().into()).collect::<Vec<Value>>().into()
+}
+
+pub fn getattr(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let arguments = arguments(data, state, parameters, 2, 2, "getattr");
+   match ident_id(data, &arguments[1]) {
+      Some(id) => get_field(data, state, &arguments[0], id),
+      None if matches!(arguments[0], Value::Struct(..) | Value::Prototype { .. }) => Value::new_err("FieldDoesNotExist"),
+      None => Value::Nil
+   }
+}
+
+pub fn setattr(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let mut arguments = arguments(data, state, parameters, 3, 3, "setattr");
+   let value = arguments.pop().unwrap();
+   let Value::Struct(instance) = &arguments[0] else { return Value::Nil };
+   let mut instance = instance.borrow_mut();
+   let Some(&index) = ident_id(data, &arguments[1]).and_then(|id| instance.field_map(data).get(&id)) else {
+      return Value::new_err("FieldDoesNotExist");
+   };
+   instance.values[index] = value.clone();
+   value
+}
+
+/// Returns true if a instance or prototype has a field, method or constant
+/// called name.
+pub fn hasattr(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let arguments = arguments(data, state, parameters, 2, 2, "hasattr");
+   let Some((prototype, variant)) = prototype_of(&arguments[0]) else { return false.into() };
+   let Some(id) = ident_id(data, &arguments[1]) else { return false.into() };
+   (field_map(data, prototype, variant).contains_key(&id)
+      || data.resolve_method(prototype, id).is_some()
+      || data.resolve_constant(prototype, id).is_some())
+   .into()
+}
+
+/// Returns the prototype of a instance, or the prototype called name such as
+/// "Point" or "Shape::Circle".
+pub fn prototype(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let arguments = arguments(data, state, parameters, 1, 1, "prototype");
+   if let Some((prototype, variant)) = prototype_of(&arguments[0]) {
+      return Value::Prototype { prototype, variant };
+   }
+   let Value::Str(name) = &arguments[0] else { return Value::Nil };
+   let (name, variant_name) = match name.split_once("::") {
+      Some((name, variant_name)) => (name, Some(variant_name)),
+      None => (&**name, None)
+   };
+   let Some(prototype) = data.prototypes.iter().position(|prototype| prototype.name == name) else { return Value::Nil };
+   let variant = match variant_name {
+      Some(variant_name) => {
+         let variants = &data.prototypes[prototype].variants;
+         let Some(variant) = variants.iter().position(|variant| variant.name == variant_name) else { return Value::Nil };
+         Some(variant)
+      },
+      None => None
+   };
+   Value::Prototype { prototype, variant }
+}
+
+/// Construct a instance of a prototype from a iterable of [name, value] pairs
+/// like a struct literal. Returns a err instead of dying if a field is
+/// missing or does not exist.
+pub fn new(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let arguments = arguments(data, state, parameters, 1, 2, "new");
+   let Value::Prototype { prototype, variant } = arguments[0] else { return Value::Nil };
+   let name = Struct { prototype, variant, values: vec![] }.name(data);
+   if variant.is_none() && !data.prototypes[prototype].variants.is_empty() {
+      return Value::new_err(&format!("{name} is a enum, construct one of its variants."));
+   }
+   let field_map = field_map(data, prototype, variant);
+   let mut values: Vec<Option<Value>> = vec![None; field_map.len()];
+   if let Some(pairs) = arguments.get(1) {
+      let Ok(mut pairs) = NativeIterator::from_value(data, pairs.clone()) else { return Value::new_err("TypeError") };
+      for pair in pairs.collect(data, state) {
+         let pair = match pair {
+            Value::List(list) => list.borrow().clone(),
+            Value::Tuple(tuple) => tuple.to_vec(),
+            _ => return Value::new_err("TypeError")
+         };
+         let Ok([field_name, value]) = <[Value; 2]>::try_from(pair) else { return Value::new_err("TypeError") };
+         let Some(&index) = ident_id(data, &field_name).and_then(|id| field_map.get(&id)) else {
+            let mut field = String::new();
+            field_name.fmt(data, &mut field);
+            return Value::new_err(&format!("{name} has no field {field}."));
+         };
+         values[index] = Some(value);
+      }
+   }
+   construct(data, state, prototype, variant, values).unwrap_or_else(|err| err)
+}
+
+/// Returns the function called by a callable and the number of its parameters
+/// which are already bound.
+fn callable_function(data: &Data, callable: &Value) -> Option<(usize, usize)> {
+   resolve_callable(data, callable.clone()).map(|(function_id, bound)| (function_id, bound.len()))
+}
+
+pub fn name_of(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let arguments = arguments(data, state, parameters, 1, 1, "name_of");
+   let Some((function_id, _)) = callable_function(data, &arguments[0]) else { return Value::Nil };
+   data.functions[function_id].name.as_str().into()
+}
+
+/// Returns the names of the parameters of a callable which are not bound.
+pub fn params_of(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let arguments = arguments(data, state, parameters, 1, 1, "params_of");
+   let Some((function_id, bound)) = callable_function(data, &arguments[0]) else { return Value::Nil };
+   let parameters = &data.functions[function_id].parameters;
+   parameters.iter().skip(bound).map(|parameter| parameter.as_str().into()).collect::<Vec<Value>>().into()
+}
+
+/// Returns the number of arguments a callable must be called with, parameters
+/// with a default value and the rest parameter are not counted.
+pub fn arity(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let arguments = arguments(data, state, parameters, 1, 1, "arity");
+   let Some((function_id, bound)) = callable_function(data, &arguments[0]) else { return Value::Nil };
+   let function = &data.functions[function_id];
+   let required = function.parameters.len() - usize::from(function.rest) - function.defaults.len();
+   required.saturating_sub(bound).into()
+}
+
+/// Returns the instance bound to a method, or nil.
+pub fn self_of(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let arguments = arguments(data, state, parameters, 1, 1, "self_of");
+   let mut callable = &arguments[0];
+   loop {
+      match callable {
+         Value::Method { instance, .. } => return Value::Struct(instance.clone()),
+         Value::Partial { callable: inner, .. } => callable = inner,
+         _ => return Value::Nil
+      }
+   }
+}
+
+/// Returns a method which calls the function or method with instance as self.
+pub fn bind(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let arguments = arguments(data, state, parameters, 2, 2, "bind");
+   let (Value::Function(function_id) | Value::Method { function_id, .. }) = arguments[0] else { return Value::Nil };
+   let Value::Struct(instance) = &arguments[1] else { return Value::Nil };
+   if data.functions[function_id].parameters.is_empty() {
+      return Value::new_err(&format!("Too many arguments to {}().", data.functions[function_id].name));
+   }
+   Value::Method { function_id, instance: instance.clone() }
+}
+
+/// Returns a callable which calls callable with arguments before the ones it
+/// is called with.
+pub fn partial(data: &Data, state: &mut State, parameters: &[Expr]) -> Value {
+   let mut arguments = arguments(data, state, parameters, 1, usize::MAX, "partial");
+   let Some((function_id, bound)) = callable_function(data, &arguments[0]) else { return Value::new_err("NotCallable") };
+   let function = &data.functions[function_id];
+   if !function.rest && function.parameters.len() < bound + arguments.len() - 1 {
+      return Value::new_err(&format!("Too many arguments to {}().", function.name));
+   }
+   let bound = arguments.split_off(1);
+   let callable = arguments.pop().unwrap();
+   if bound.is_empty() {
+      return callable;
+   }
+   Value::Partial { callable: Rc::new(callable), arguments: bound.into() }
+}
@@ -1,18 +1,21 @@
-use std::{cell::RefCell, iter::repeat, ops::Drop, process::exit, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, iter::repeat_n, ops::Drop, process::exit, rc::Rc};
 
-use crate::{ir::*, plugins::plugin_call, value::*};
+use crate::{ir::*, iter::NativeIterator, misc::range_len, plugins::plugin_call, value::*};
 
 /// This struct stores mutable state of the program.
 #[derive(Debug)]
 pub struct State {
    /// Index to Data.functions
    variables: Vec<Value>,
-   variables_begin: usize
+   variables_begin: usize,
+   /// (prototype, constant ident id) -> value of the constant, None while it
+   /// is being evaluated.
+   constants: HashMap<(usize, usize), Option<Value>>
 }
 
 impl State {
    pub fn new() -> Self {
-      Self { variables: vec![], variables_begin: 0 }
+      Self { variables: vec![], variables_begin: 0, constants: HashMap::new() }
    }
 
    pub fn die(&mut self, data: &Data, value: Value, range: Option<&Range>) -> ! {
@@ -26,10 +29,161 @@ impl State {
    }
 }
 
+/// Saved state of a suspended generator. Instead of using the Rust call
+/// stack, the position of execution is stored as a cursor for every block
+/// entered, so that execution can be suspended at a yield and resumed later.
+#[derive(Debug)]
+pub struct Frame {
+   function_id: usize,
+   /// Variables of the function while it is suspended.
+   variables: Vec<Value>,
+   /// The first cursor is in the function body, every other cursor is in a
+   /// block of the statement at the cursor before it.
+   cursors: Vec<Cursor>
+}
+
+#[derive(Debug, Default)]
+struct Cursor {
+   /// Index of the current statement in the block.
+   index: usize,
+   /// If the current statement is a branch, whether the otherwise block was
+   /// entered.
+   otherwise: bool,
+   /// If the current statement is a match, the index of the arm entered.
+   arm: usize,
+   /// If the current statement is a for loop, its iterator.
+   iterator: Option<NativeIterator>
+}
+
+impl Frame {
+   fn new(function_id: usize, variables: Vec<Value>) -> Self {
+      Self { function_id, variables, cursors: vec![Cursor::default()] }
+   }
+
+   /// Returns the block which the cursor at depth is in.
+   fn block<'a>(&self, data: &'a Data, depth: usize) -> &'a Block {
+      let mut block = &data.functions[self.function_id].body;
+      for cursor in &self.cursors[..depth] {
+         block = match &block[cursor.index] {
+            Exec::While { block, .. } | Exec::DoWhile { block, .. } | Exec::ForLoop { block, .. } => block,
+            Exec::Branch { otherwise, .. } if cursor.otherwise => otherwise,
+            Exec::Branch { then, .. } => then,
+            Exec::Match { arms, .. } => &arms[cursor.arm].block,
+            _ => unreachable!()
+         };
+      }
+      block
+   }
+
+   /// Run until the next yield and return the yielded value, or iterend if the
+   /// function returned.
+   pub fn resume(&mut self, data: &Data, state: &mut State) -> Value {
+      if self.cursors.is_empty() {
+         return Value::IterEnd;
+      }
+      let new_variables_begin = state.variables.len();
+      state.variables.append(&mut self.variables);
+      let old_variables_begin = state.variables_begin;
+      state.variables_begin = new_variables_begin;
+      let ret = self.run(data, state);
+      if self.cursors.is_empty() {
+         // Drop the variables of a finished generator.
+         state.variables.truncate(new_variables_begin);
+      } else {
+         self.variables.extend(state.variables.drain(new_variables_begin..));
+      }
+      state.variables_begin = old_variables_begin;
+      ret
+   }
+
+   /// Advance the for loop at the top cursor and enter its block, or move past
+   /// it if the iterator is exhausted.
+   fn next_iteration(&mut self, data: &Data, state: &mut State, variable: &Reference) {
+      let cursor = self.cursors.last_mut().unwrap();
+      let next = match &mut cursor.iterator {
+         Some(iterator) => iterator.next(data, state),
+         None => Value::IterEnd
+      };
+      if let Value::IterEnd = next {
+         cursor.iterator = None;
+         cursor.index += 1;
+      } else {
+         Expr::set_variable(state, variable, &next);
+         self.cursors.push(Cursor::default());
+      }
+   }
+
+   fn run(&mut self, data: &Data, state: &mut State) -> Value {
+      loop {
+         let depth = self.cursors.len() - 1;
+         let block = self.block(data, depth);
+         let index = self.cursors[depth].index;
+         let Some(stmt) = block.get(index) else {
+            // The block has finished, continue the statement which entered it.
+            self.cursors.pop();
+            if self.cursors.is_empty() {
+               return Value::IterEnd;
+            }
+            match &self.block(data, depth - 1)[self.cursors[depth - 1].index] {
+               Exec::While { condition, .. } | Exec::DoWhile { condition, .. } =>
+                  if condition.eval(data, state).is_truthy() {
+                     self.cursors.push(Cursor::default());
+                  } else {
+                     self.cursors[depth - 1].index += 1;
+                  },
+               Exec::ForLoop { variable, .. } => self.next_iteration(data, state, variable),
+               _ => self.cursors[depth - 1].index += 1
+            }
+            continue;
+         };
+         match stmt {
+            Exec::Yield { expr } => {
+               let value = expr.eval(data, state);
+               self.cursors[depth].index += 1;
+               return value;
+            },
+            Exec::Return { expr } => {
+               expr.eval(data, state);
+               self.cursors.clear();
+               return Value::IterEnd;
+            },
+            Exec::Expr { expr } => {
+               expr.eval(data, state);
+               self.cursors[depth].index += 1;
+            },
+            Exec::While { condition, .. } =>
+               if condition.eval(data, state).is_truthy() {
+                  self.cursors.push(Cursor::default());
+               } else {
+                  self.cursors[depth].index += 1;
+               },
+            Exec::DoWhile { .. } => self.cursors.push(Cursor::default()),
+            Exec::Branch { condition, .. } => {
+               self.cursors[depth].otherwise = !condition.eval(data, state).is_truthy();
+               self.cursors.push(Cursor::default());
+            },
+            Exec::Match { expr, arms } => match Exec::find_arm(data, state, expr, arms) {
+               Some(arm) => {
+                  self.cursors[depth].arm = arm;
+                  self.cursors.push(Cursor::default());
+               },
+               None => self.cursors[depth].index += 1
+            },
+            Exec::ForLoop { variable, iterator, .. } => {
+               let iterator = iterator.eval(data, state);
+               let iterator = NativeIterator::from_value(data, iterator).unwrap_or_else(|err| state.die(data, err, None));
+               self.cursors[depth].iterator = Some(iterator);
+               self.next_iteration(data, state, variable);
+            }
+         }
+      }
+   }
+}
+
 impl Drop for State {
    fn drop(&mut self) {
       assert!(self.variables_begin == 0);
-      assert!(self.variables.len() == 0);
+      assert!(self.variables.is_empty());
    }
 }
 
@@ -72,19 +226,35 @@ impl Exec {
       }
    }
 
+   /// Returns the index of the first arm which matches the value of expr.
+   fn find_arm(data: &Data, state: &mut State, expr: &Expr, arms: &[MatchArm]) -> Option<usize> {
+      let value = expr.eval(data, state);
+      arms.iter().position(|arm| {
+         arm.pattern.matches(data, state, &value) && arm.guard.as_ref().is_none_or(|guard| guard.eval(data, state).is_truthy())
+      })
+   }
+
+   fn match_(data: &Data, state: &mut State, expr: &Expr, arms: &[MatchArm]) -> Option<Value> {
+      let arm = Exec::find_arm(data, state, expr, arms)?;
+      Exec::exec_all(data, state, &arms[arm].block)
+   }
+
    /// Evaluate expression and ignore it's return value and return None.
    fn expr(data: &Data, state: &mut State, expr: &Expr) -> Option<Value> {
       expr.eval(data, state);
       None
    }
 
-   fn for_loop(data: &Data, state: &mut State, variable: &Reference, iterator: &Expr, block: &Block) -> Option<Value> {
-      let iterator = iterator.eval(data, state);
-      let Value::Method { function_id: next_method, .. } = iterator.get_field(data, data.reserved_idents.next) else {
-         return None;
-      };
+   /// Run block for every value returned by next until it returns iterend.
+   fn for_each(
+      data: &Data,
+      state: &mut State,
+      variable: &Reference,
+      block: &Block,
+      mut next: impl FnMut(&Data, &mut State) -> Value
+   ) -> Option<Value> {
       loop {
-         let next = call(data, state, next_method, &vec![], Some(iterator.clone())).unwrap_or(Value::Nil);
+         let next = next(data, state);
          if let Value::IterEnd = next {
             break;
          }
@@ -96,14 +266,114 @@ impl Exec {
       None
    }
 
+   /// Fast path for loops over a range, which avoids calling a next method.
+   fn range(
+      data: &Data,
+      state: &mut State,
+      variable: &Reference,
+      block: &Block,
+      start: i64,
+      stop: i64,
+      step: i64
+   ) -> Option<Value> {
+      let mut i = start;
+      for _ in 0..range_len(start, stop, step) {
+         Expr::set_variable(state, variable, &Value::Int(i));
+         if let Some(v) = Exec::exec_all(data, state, block) {
+            return Some(v);
+         }
+         i = i.wrapping_add(step);
+      }
+      None
+   }
+
+   fn for_loop(data: &Data, state: &mut State, variable: &Reference, iterator: &Expr, block: &Block) -> Option<Value> {
+      let iterator = iterator.eval(data, state);
+      if let Value::Range { start, stop, step } = iterator {
+         return Exec::range(data, state, variable, block, start, stop, step);
+      }
+      let mut iterator = NativeIterator::from_value(data, iterator).unwrap_or_else(|err| state.die(data, err, None));
+      Exec::for_each(data, state, variable, block, |data, state| iterator.next(data, state))
+   }
+
    fn exec(&self, data: &Data, state: &mut State) -> Option<Value> {
       match self {
          Exec::While { condition, block } => Exec::while_(data, state, condition, block),
          Exec::ForLoop { variable, iterator, block } => Exec::for_loop(data, state, variable, iterator, block),
          Exec::DoWhile { block, condition } => Exec::dowhile(data, state, block, condition),
          Exec::Return { expr } => Some(expr.eval(data, state)),
+         Exec::Yield { .. } => unreachable!("yield outside of a generator"),
          Exec::Expr { expr } => Exec::expr(data, state, expr),
-         Exec::Branch { condition, then, otherwise } => Exec::branch(data, state, condition, then, otherwise)
+         Exec::Branch { condition, then, otherwise } => Exec::branch(data, state, condition, then, otherwise),
+         Exec::Match { expr, arms } => Exec::match_(data, state, expr, arms)
+      }
+   }
+}
+
+impl Pattern {
+   /// Returns whether value matches the pattern, assigning the bindings of
+   /// the pattern as they are matched.
+   fn matches(&self, data: &Data, state: &mut State, value: &Value) -> bool {
+      match self {
+         Pattern::Wildcard => true,
+         Pattern::Literal { literal } => Value::from(literal).eq(value),
+         Pattern::Type { name } => match value {
+            // Instances also match the names of the parents of their prototype.
+            Value::Struct(instance) => {
+               let mut prototype = Some(instance.borrow().prototype);
+               while let Some(id) = prototype {
+                  if data.prototypes[id].name == *name {
+                     return true;
+                  }
+                  prototype = data.prototypes[id].parent;
+               }
+               false
+            },
+            _ => matches!(value.clone().typename(data), Value::Str(typename) if *typename == **name)
+         },
+         Pattern::Binding { variable, pattern } => {
+            let matched = pattern.matches(data, state, value);
+            if matched {
+               Expr::set_variable(state, variable, value);
+            }
+            matched
+         },
+         Pattern::Struct { prototype, variant, fields } => {
+            let Value::Struct(instance) = value else { return false };
+            {
+               let instance = instance.borrow();
+               if !data.inherits(instance.prototype, *prototype) || (variant.is_some() && instance.variant != *variant) {
+                  return false;
+               }
+            }
+            fields.iter().all(|FieldPattern { field_id, pattern }| {
+               let field = {
+                  let instance = instance.borrow();
+                  let Some(&index) = instance.field_map(data).get(field_id) else { return false };
+                  instance.values[index].clone()
+               };
+               pattern.matches(data, state, &field)
+            })
+         },
+         Pattern::Sequence { tuple, before, rest, after } => {
+            let items: Vec<Value> = match value {
+               Value::Tuple(items) if *tuple => items.to_vec(),
+               Value::List(items) if !*tuple => items.borrow().clone(),
+               _ => return false
+            };
+            let count = before.len() + after.len();
+            if items.len() < count || (rest.is_none() && items.len() != count) {
+               return false;
+            }
+            let rest_count = items.len() - count;
+            let mut items = items.into_iter();
+            before.iter().all(|pattern| pattern.matches(data, state, &items.next().unwrap()))
+               && {
+                  let rest_items: Vec<Value> = items.by_ref().take(rest_count).collect();
+                  rest.as_ref().is_none_or(|rest| rest.matches(data, state, &rest_items.into()))
+               }
+               && after.iter().all(|pattern| pattern.matches(data, state, &items.next().unwrap()))
+         }
       }
    }
 }
@@ -148,27 +418,172 @@ impl Expr {
       }
    }
 
-   fn make_list(parameters: &Vec<Expr>, data: &Data, state: &mut State) -> Value {
+   /// Die if the builtin was not given exactly count parameters.
+   fn check_arity(data: &Data, state: &mut State, parameters: &[Expr], count: usize, name: &str) {
+      if parameters.len() != count {
+         state.die(data, Value::new_err(&format!("Wrong number of arguments to {name}().")), None)
+      }
+   }
+
+   /// Read a line from stdin, or from the file handle if one is given.
+   fn read_line(parameters: &[Expr], data: &Data, state: &mut State) -> Value {
+      match parameters {
+         [] => Value::read_line(),
+         [file] => file.eval(data, state).read_file_line(),
+         _ => state.die(data, Value::new_err("Wrong number of arguments to read_line()."), None)
+      }
+   }
+
+   /// range(stop), range(start, stop) or range(start, stop, step).
+   fn range(parameters: &[Expr], data: &Data, state: &mut State) -> Value {
+      match parameters {
+         [stop] => Value::range(Value::Int(0), stop.eval(data, state), Value::Int(1)),
+         [start, stop] => Value::range(start.eval(data, state), stop.eval(data, state), Value::Int(1)),
+         [start, stop, step] => Value::range(start.eval(data, state), stop.eval(data, state), step.eval(data, state)),
+         _ => state.die(data, Value::new_err("Wrong number of arguments to range()."), None)
+      }
+   }
+
+   fn seek(parameters: &[Expr], data: &Data, state: &mut State) -> Value {
+      match parameters {
+         [file, offset] => file.eval(data, state).seek(offset.eval(data, state), "start".into()),
+         [file, offset, whence] => file.eval(data, state).seek(offset.eval(data, state), whence.eval(data, state)),
+         _ => state.die(data, Value::new_err("Wrong number of arguments to seek()."), None)
+      }
+   }
+
+   /// slice(value, start), slice(value, start, stop) or
+   /// slice(value, start, stop, step).
+   fn slice(parameters: &[Expr], data: &Data, state: &mut State) -> Value {
+      match parameters {
+         [value, start] => value.eval(data, state).slice(start.eval(data, state), Value::Nil, Value::Nil),
+         [value, start, stop] => value.eval(data, state).slice(start.eval(data, state), stop.eval(data, state), Value::Nil),
+         [value, start, stop, step] =>
+            value.eval(data, state).slice(start.eval(data, state), stop.eval(data, state), step.eval(data, state)),
+         _ => state.die(data, Value::new_err("Wrong number of arguments to slice()."), None)
+      }
+   }
+
+   /// Membership test, structs are tested by calling their __contains__ method.
+   fn contains(container: &Expr, element: &Expr, data: &Data, state: &mut State) -> Value {
+      let (container, element) = (container.eval(data, state), element.eval(data, state));
+      if let Value::Struct(instance) = &container {
+         let prototype = instance.borrow().prototype;
+         if let Some(function_id) = data.reserved_idents.__contains__.and_then(|id| data.resolve_method(prototype, id)) {
+            return call_function(data, state, function_id, vec![container, element]).bool();
+         }
+      }
+      container.contains(element)
+   }
+
+   /// Push the items of any iterable to the end of the list.
+   fn extend(list: &Expr, iterable: &Expr, data: &Data, state: &mut State) -> Value {
+      let (list, iterable) = (list.eval(data, state), iterable.eval(data, state));
+      let Value::List(list) = list else { return Value::Nil };
+      // Collect first, so that extending a list with itself terminates.
+      let items = match NativeIterator::from_value(data, iterable) {
+         Ok(mut iterator) => iterator.collect(data, state),
+         Err(err) => return err
+      };
+      list.borrow_mut().extend(items);
+      Value::Nil
+   }
+
+   fn stdin_lines(parameters: &[Expr], data: &Data, state: &mut State) -> Value {
+      Expr::check_arity(data, state, parameters, 0, "stdin_lines");
+      Value::Iterator(Rc::new(RefCell::new(NativeIterator::StdinLines { failed: false })))
+   }
+
+   fn make_list(parameters: &[Expr], data: &Data, state: &mut State) -> Value {
       Value::List(Rc::new(RefCell::new(parameters.iter().map(|v| v.eval(data, state)).collect())))
    }
 
-   fn make_struct(prototype: &usize, values: &Vec<Expr>, data: &Data, state: &mut State) -> Value {
-      Struct { prototype: *prototype, values: values.iter().map(|v| v.eval(data, state)).collect() }.into()
+   /// Assign each item of a tuple or list to a variable, dies if the number of
+   /// items is not the number of variables.
+   fn unpack(data: &Data, state: &mut State, target: &Target, expr: &Expr) -> Value {
+      let value = expr.eval(data, state);
+      Expr::destructure(data, state, target, value.clone());
+      value
+   }
+
+   /// Assign the parts of value to the variables of target, dies if the
+   /// value does not have the shape of the target.
+   fn destructure(data: &Data, state: &mut State, target: &Target, value: Value) {
+      match target {
+         Target::Variable { variable } => Expr::set_variable(state, variable, &value),
+         Target::Sequence { before, rest, after } => {
+            let items: Vec<Value> = match &value {
+               Value::Tuple(tuple) => tuple.to_vec(),
+               Value::List(list) => list.borrow().clone(),
+               _ => state.die(data, Value::new_err("TypeError"), None)
+            };
+            let count = before.len() + after.len();
+            if items.len() < count || (rest.is_none() && items.len() != count) {
+               let expected = if rest.is_some() { format!("at least {count}") } else { count.to_string() };
+               let message = format!("Cannot unpack {} values into {expected} variables.", items.len());
+               state.die(data, Value::new_err(&message), None)
+            }
+            let rest_count = items.len() - count;
+            let mut items = items.into_iter();
+            for target in before {
+               Expr::destructure(data, state, target, items.next().unwrap());
+            }
+            let rest_items: Vec<Value> = items.by_ref().take(rest_count).collect();
+            if let Some(rest) = rest {
+               Expr::set_variable(state, rest, &rest_items.into());
+            }
+            for target in after {
+               Expr::destructure(data, state, target, items.next().unwrap());
+            }
+         },
+         Target::Struct { fields } => {
+            let Value::Struct(instance) = &value else { state.die(data, Value::new_err("TypeError"), None) };
+            for FieldTarget { field_id, target } in fields {
+               let field = {
+                  let instance = instance.borrow();
+                  match instance.field_map(data).get(field_id) {
+                     Some(&index) => instance.values[index].clone(),
+                     None => {
+                        let message = format!("{} has no field {}.", instance.name(data), data.ident_map[field_id]);
+                        state.die(data, Value::new_err(&message), None)
+                     }
+                  }
+               };
+               Expr::destructure(data, state, target, field);
+            }
+         }
+      }
+   }
+
+   fn make_struct(
+      prototype: &usize,
+      variant: &Option<usize>,
+      values: &[Option<Expr>],
+      data: &Data,
+      state: &mut State
+   ) -> Value {
+      let values = values.iter().map(|value| value.as_ref().map(|value| value.eval(data, state))).collect();
+      construct(data, state, *prototype, *variant, values).unwrap_or_else(|err| state.die(data, err, None))
    }
 
    fn set_field(value: &Expr, data: &Data, state: &mut State, instance: &Expr, field_id: &usize) -> Value {
       let value = value.eval(data, state);
-      match instance.eval(data, state) {
-         Value::Struct(instance) => {
-            let mut instance = instance.borrow_mut();
-            let prototype = &data.prototypes[instance.prototype];
-            instance.values[prototype.field_map[field_id]] = value.clone();
-         },
-         _ => {}
+      if let Value::Struct(instance) = instance.eval(data, state) {
+         let mut instance = instance.borrow_mut();
+         if let Some(&index) = instance.field_map(data).get(field_id) {
+            instance.values[index] = value.clone();
+         }
       }
       value
    }
 
+   fn super_method(data: &Data, state: &mut State, prototype: usize, method_id: usize, instance: &Expr) -> Value {
+      match (instance.eval(data, state), data.resolve_method(prototype, method_id)) {
+         (Value::Struct(instance), Some(function_id)) => Value::Method { function_id, instance },
+         _ => Value::new_err("FieldDoesNotExist")
+      }
+   }
+
    fn die(data: &Data, state: &mut State, expr: &Expr, range: &Range) -> ! {
       let err = expr.eval(data, state);
       state.die(data, err, Some(range))
@@ -177,35 +592,28 @@ impl Expr {
    fn or_die(data: &Data, state: &mut State, expr: &Expr, range: &Range) -> Value {
       match expr.eval(data, state) {
          Value::Err(err) => state.die(data, Value::Err(err), Some(range)),
-         a => return a
+         a => a
       }
    }
 
-   fn call(data: &Data, state: &mut State, callable: &Expr, parameters: &Vec<Expr>) -> Value {
+   fn call(data: &Data, state: &mut State, callable: &Expr, parameters: &[Expr], keywords: &[KeywordArgument]) -> Value {
       let callable = callable.eval(data, state);
-      if let Value::Struct(instance) = &callable {
-         let function_id = data.prototypes[instance.borrow().prototype].method_map[&data.reserved_idents.__call__];
-         return call(data, state, function_id, parameters, Some(Value::Struct(instance.clone()))).unwrap_or(Value::Nil);
+      if let Value::Iterator(iterator) = &callable {
+         if !keywords.is_empty() {
+            state.die(data, Value::new_err(&format!("Unknown argument {} to next().", keywords[0].name)), None)
+         }
+         Expr::check_arity(data, state, parameters, 0, "next");
+         return NativeIterator::next_shared(iterator, data, state);
       }
-      match callable {
-         Value::Function(function_id) => call(data, state, function_id, parameters, None).unwrap_or(Value::Nil),
-         Value::Method { function_id, instance } =>
-            call(data, state, function_id, parameters, Some(Value::Struct(instance))).unwrap_or(Value::Nil),
-         _ => Value::new_err("NotCallable")
+      match resolve_callable(data, callable) {
+         Some((function_id, bound)) => call(data, state, function_id, parameters, keywords, bound).unwrap_or(Value::Nil),
+         None => Value::new_err("NotCallable")
       }
    }
 
    pub fn eval(&self, data: &Data, state: &mut State) -> Value {
       match self {
-         Expr::Literal { literal } => match literal {
-            // FIXME: Cache literals.
-            Literal::Nil => Value::Nil,
-            Literal::IterEnd => Value::IterEnd,
-            Literal::Bool(bool) => Value::Bool(*bool),
-            Literal::Int(int) => Value::Int(*int),
-            Literal::Float(float) => Value::Float(*float),
-            Literal::Str(str) => Value::Str(str.clone().into())
-         },
+         Expr::Literal { literal } => literal.into(),
          Expr::Reference { reference: refer } => match refer {
             Reference::Variable(id) => Expr::get_variable(state, id),
             &Reference::Function(function_id) => Value::Function(function_id)
@@ -220,15 +628,30 @@ impl Expr {
             UnaryOperator::Int => expr.eval(data, state).int(),
             UnaryOperator::Float => expr.eval(data, state).float(),
             UnaryOperator::Str => expr.eval(data, state).str(data),
+            UnaryOperator::Repr => expr.eval(data, state).repr(data),
             UnaryOperator::Len => expr.eval(data, state).len(),
             UnaryOperator::Print => expr.eval(data, state).print(data),
-            UnaryOperator::Read => expr.eval(data, state).read()
+            UnaryOperator::Put => expr.eval(data, state).put(data),
+            UnaryOperator::EPrint => expr.eval(data, state).eprint(data),
+            UnaryOperator::Input => expr.eval(data, state).input(data),
+            UnaryOperator::Read => expr.eval(data, state).read(),
+            UnaryOperator::Flush => expr.eval(data, state).flush(),
+            UnaryOperator::Close => expr.eval(data, state).close(),
+            UnaryOperator::Hash => expr.eval(data, state).hash_value(),
+            UnaryOperator::Variant => expr.eval(data, state).variant(data),
+            UnaryOperator::Pop => expr.eval(data, state).pop(),
+            UnaryOperator::Clear => expr.eval(data, state).clear(),
+            UnaryOperator::Reverse => expr.eval(data, state).reverse(),
+            UnaryOperator::Copy => expr.eval(data, state).copy(),
+            UnaryOperator::DeepCopy => expr.eval(data, state).deep_copy()
          },
          Expr::BinaryOperation { operator, left, right } => match operator {
             BinaryOperator::Add => left.eval(data, state).add(right.eval(data, state)),
             BinaryOperator::Sub => left.eval(data, state).sub(right.eval(data, state)),
             BinaryOperator::Mul => left.eval(data, state).mul(right.eval(data, state)),
             BinaryOperator::Div => left.eval(data, state).div(right.eval(data, state)),
+            BinaryOperator::FloorDiv => left.eval(data, state).floordiv(right.eval(data, state)),
+            BinaryOperator::Pow => left.eval(data, state).pow(right.eval(data, state)),
             BinaryOperator::Modulo => left.eval(data, state).modulo(right.eval(data, state)),
             BinaryOperator::GetItem => left.eval(data, state).getitem(right.eval(data, state)),
             BinaryOperator::Eq => Value::Bool(left.eval(data, state).eq(&right.eval(data, state))),
@@ -246,43 +669,214 @@ impl Expr {
             BinaryOperator::Remove => left.eval(data, state).remove(right.eval(data, state)),
             BinaryOperator::Index => left.eval(data, state).index(right.eval(data, state)),
             BinaryOperator::Join => left.eval(data, state).join(data, right.eval(data, state)),
-            BinaryOperator::Write => left.eval(data, state).write(right.eval(data, state))
+            BinaryOperator::Write => left.eval(data, state).write(right.eval(data, state)),
+            BinaryOperator::Open => left.eval(data, state).open(right.eval(data, state)),
+            BinaryOperator::Read => left.eval(data, state).read_chars(right.eval(data, state)),
+            BinaryOperator::Extend => Expr::extend(left, right, data, state),
+            BinaryOperator::Contains => Expr::contains(left, right, data, state),
+            BinaryOperator::Count => left.eval(data, state).count(right.eval(data, state))
          },
          Expr::TernaryOperation { operator, first, second, third } => match operator {
             TernaryOperator::Branch => first.branch(data, state, second, third),
-            TernaryOperator::SetItem => first.eval(data, state).setitem(second.eval(data, state), third.eval(data, state))
+            TernaryOperator::SetItem => first.eval(data, state).setitem(second.eval(data, state), third.eval(data, state)),
+            TernaryOperator::Insert => first.eval(data, state).insert(second.eval(data, state), third.eval(data, state))
          },
          Expr::NaryOperation { operator, parameters } => match operator {
-            NaryOperator::List => Expr::make_list(parameters, data, state)
+            NaryOperator::List => Expr::make_list(parameters, data, state),
+            NaryOperator::Tuple => Value::Tuple(parameters.iter().map(|v| v.eval(data, state)).collect()),
+            NaryOperator::ReadLine => Expr::read_line(parameters, data, state),
+            NaryOperator::StdinLines => Expr::stdin_lines(parameters, data, state),
+            NaryOperator::Seek => Expr::seek(parameters, data, state),
+            NaryOperator::Range => Expr::range(parameters, data, state),
+            NaryOperator::Slice => Expr::slice(parameters, data, state)
          },
-         Expr::Call { callable, parameters } => Expr::call(data, state, callable, parameters),
+         Expr::Call { callable, parameters, keywords } => Expr::call(data, state, callable, parameters, keywords),
          Expr::Plugin { id, parameters } => plugin_call(data, state, *id, parameters),
          Expr::SetVar { variable, expr } => {
             let value = expr.eval(data, state);
             Expr::set_variable(state, variable, &value);
             value
          },
-         Expr::Struct { prototype, values } => Expr::make_struct(prototype, values, data, state),
+         Expr::Unpack { target, expr } => Expr::unpack(data, state, target, expr),
+         Expr::Struct { prototype, variant, values } => Expr::make_struct(prototype, variant, values, data, state),
          Expr::SetField { instance, field_id, value } => Expr::set_field(value, data, state, instance, field_id),
-         Expr::GetField { instance, field_id } => instance.eval(data, state).get_field(data, *field_id),
+         Expr::GetField { instance, field_id } => {
+            let instance = instance.eval(data, state);
+            get_field(data, state, &instance, *field_id)
+         },
+         Expr::Super { prototype, method_id, instance } => Expr::super_method(data, state, *prototype, *method_id, instance),
+         &Expr::Prototype { prototype, variant } => Value::Prototype { prototype, variant },
          Expr::Die { expr, range } => Expr::die(data, state, expr, range),
          Expr::OrDie { expr, range } => Expr::or_die(data, state, expr, range)
       }
    }
 }
 
-fn call(data: &Data, state: &mut State, function_id: usize, parameters: &Vec<Expr>, instance: Option<Value>) -> Option<Value> {
-   let function = &data.functions[function_id];
-   assert!(function.parameters.len() == parameters.len() + if instance.is_some() { 1 } else { 0 });
+/// Construct a instance, values are in the order of the field map and None for
+/// fields which take their default value. The __init__ method is called after
+/// the instance is created, returns the err if __init__ returned one. Returns
+/// Err if a field without a default is missing.
+pub fn construct(
+   data: &Data,
+   state: &mut State,
+   prototype: usize,
+   variant: Option<usize>,
+   values: Vec<Option<Value>>
+) -> Result<Value, Value> {
+   let definition = &data.prototypes[prototype];
+   let field_map = match variant {
+      Some(variant) => &definition.variants[variant].field_map,
+      None => &definition.field_map
+   };
+   let mut fields = Vec::with_capacity(values.len());
+   for (index, value) in values.into_iter().enumerate() {
+      let value = match value {
+         Some(value) => value,
+         None => {
+            let field_id = field_map.iter().find(|(_, &i)| i == index).map(|(&id, _)| id).unwrap();
+            match definition.defaults.get(&field_id) {
+               Some(default) => default.eval(data, state),
+               None => {
+                  let name = Struct { prototype, variant, values: vec![] }.name(data);
+                  return Err(Value::new_err(&format!("Missing field {} of {name}.", data.ident_map[&field_id])));
+               }
+            }
+         }
+      };
+      fields.push(value);
+   }
+   let instance: Value = Struct { prototype, variant, values: fields }.into();
+   if let Some(function_id) = data.reserved_idents.init.and_then(|id| data.resolve_method(prototype, id)) {
+      if let ret @ Value::Err(..) = call_function(data, state, function_id, vec![instance.clone()]) {
+         return Ok(ret);
+      }
+   }
+   Ok(instance)
+}
+
+/// Get a field, method or constant of a instance or prototype.
+pub fn get_field(data: &Data, state: &mut State, value: &Value, field_id: usize) -> Value {
+   let prototype = match value {
+      Value::Struct(instance) => instance.borrow().prototype,
+      &Value::Prototype { prototype, .. } => prototype,
+      _ => return value.get_field(data, field_id)
+   };
+   match data.resolve_constant(prototype, field_id) {
+      Some(prototype) => constant(data, state, prototype, field_id),
+      None => value.get_field(data, field_id)
+   }
+}
+
+/// Returns the value of a constant, evaluating it if it is accessed for the
+/// first time.
+fn constant(data: &Data, state: &mut State, prototype: usize, constant_id: usize) -> Value {
+   match state.constants.get(&(prototype, constant_id)) {
+      Some(Some(value)) => return value.clone(),
+      Some(None) => {
+         let name = format!("{}.{}", data.prototypes[prototype].name, data.ident_map[&constant_id]);
+         state.die(data, Value::new_err(&format!("Constant {name} depends on itself.")), None)
+      },
+      None => {}
+   }
+   state.constants.insert((prototype, constant_id), None);
+   let value = data.prototypes[prototype].constants[&constant_id].eval(data, state);
+   state.constants.insert((prototype, constant_id), Some(value.clone()));
+   value
+}
+
+/// Returns the function called by a callable value and the arguments bound to
+/// it, starting with the instance for methods.
+pub fn resolve_callable(data: &Data, callable: Value) -> Option<(usize, Vec<Value>)> {
+   match callable {
+      Value::Function(function_id) => Some((function_id, vec![])),
+      Value::Method { function_id, instance } => Some((function_id, vec![Value::Struct(instance)])),
+      Value::Struct(instance) => {
+         let function_id = data.resolve_method(instance.borrow().prototype, data.reserved_idents.__call__)?;
+         Some((function_id, vec![Value::Struct(instance)]))
+      },
+      Value::Partial { callable, arguments } => {
+         let (function_id, mut bound) = resolve_callable(data, (*callable).clone())?;
+         bound.extend(arguments.iter().cloned());
+         Some((function_id, bound))
+      },
+      _ => None
+   }
+}
+
+fn call(
+   data: &Data,
+   state: &mut State,
+   function_id: usize,
+   parameters: &[Expr],
+   keywords: &[KeywordArgument],
+   mut arguments: Vec<Value>
+) -> Option<Value> {
+   arguments.extend(parameters.iter().map(|parameter| parameter.eval(data, state)));
+   let keywords = keywords.iter().map(|keyword| (keyword.name.as_str(), keyword.expr.eval(data, state))).collect();
+   let arguments = bind_arguments(data, state, function_id, arguments, keywords);
    let new_variables_begin = state.variables.len();
-   if let Some(instance) = instance {
-      state.variables.push(instance);
+   state.variables.extend(arguments);
+   enter(data, state, function_id, new_variables_begin)
+}
+
+/// Match the arguments of a call to the parameters of the function. Extra
+/// arguments are collected into the rest parameter, and parameters which are
+/// not given take their default value.
+fn bind_arguments(
+   data: &Data,
+   state: &mut State,
+   function_id: usize,
+   mut arguments: Vec<Value>,
+   keywords: Vec<(&str, Value)>
+) -> Vec<Value> {
+   let function = &data.functions[function_id];
+   let count = function.parameters.len() - usize::from(function.rest);
+   let rest = function.rest.then(|| arguments.split_off(count.min(arguments.len())));
+   if count < arguments.len() {
+      let required = count - function.defaults.len();
+      let expected = if required == count { count.to_string() } else { format!("{required} to {count}") };
+      let message = format!("Too many arguments to {}(), expected {expected} but got {}.", function.name, arguments.len());
+      state.die(data, Value::new_err(&message), None)
    }
-   for v in parameters {
-      let v = v.eval(data, state);
-      state.variables.push(v);
+   let mut values: Vec<Option<Value>> = arguments.into_iter().map(Some).collect();
+   values.resize(count, None);
+   for (name, value) in keywords {
+      let Some(index) = function.parameters[..count].iter().position(|parameter| parameter == name) else {
+         state.die(data, Value::new_err(&format!("Unknown argument {name} to {}().", function.name)), None)
+      };
+      if values[index].is_some() {
+         state.die(data, Value::new_err(&format!("Argument {name} to {}() is given more than once.", function.name)), None)
+      }
+      values[index] = Some(value);
+   }
+   let mut arguments = Vec::with_capacity(function.parameters.len());
+   for (index, value) in values.into_iter().enumerate() {
+      let value = match (value, function.defaults.get(&index)) {
+         (Some(value), _) => value,
+         (None, Some(default)) => default.eval(data, state),
+         (None, None) => {
+            let message = format!("Missing argument {} to {}().", function.parameters[index], function.name);
+            state.die(data, Value::new_err(&message), None)
+         }
+      };
+      arguments.push(value);
+   }
+   if let Some(rest) = rest {
+      arguments.push(rest.into());
+   }
+   arguments
+}
+
+/// Run a function whose parameters have been pushed starting at
+/// new_variables_begin.
+fn enter(data: &Data, state: &mut State, function_id: usize, new_variables_begin: usize) -> Option<Value> {
+   let function = &data.functions[function_id];
+   if function.generator {
+      let mut variables: Vec<Value> = state.variables.drain(new_variables_begin..).collect();
+      variables.resize(function.variables.len(), Value::Nil);
+      return Some(NativeIterator::Generator(Box::new(Frame::new(function_id, variables))).into());
    }
-   state.variables.extend(repeat(Value::Nil).take(function.variables.len() - function.parameters.len()));
+   state.variables.extend(repeat_n(Value::Nil, function.variables.len() - function.parameters.len()));
    let old_variables_begin = state.variables_begin;
    state.variables_begin = new_variables_begin;
    let ret = Exec::exec_all(data, state, &function.body);
@@ -291,11 +885,35 @@ fn call(data: &Data, state: &mut State, function_id: usize, parameters: &Vec<Exp
    ret
 }
 
-pub fn call_by_name(data: &Data, state: &mut State, function_name: &str, parameters: &Vec<Expr>) -> Option<Value> {
+/// Call a function with already evaluated arguments, including the instance
+/// for methods.
+pub fn call_function(data: &Data, state: &mut State, function_id: usize, arguments: Vec<Value>) -> Value {
+   let arguments = bind_arguments(data, state, function_id, arguments, vec![]);
+   let new_variables_begin = state.variables.len();
+   state.variables.extend(arguments);
+   enter(data, state, function_id, new_variables_begin).unwrap_or(Value::Nil)
+}
+
+/// Call a callable value with already evaluated arguments, dispatches the
+/// same way as a call expression.
+pub fn call_value(data: &Data, state: &mut State, callable: &Value, arguments: Vec<Value>) -> Value {
+   if let Value::Iterator(iterator) = callable {
+      if arguments.is_empty() {
+         return NativeIterator::next_shared(iterator, data, state);
+      }
+   }
+   let Some((function_id, mut bound)) = resolve_callable(data, callable.clone()) else {
+      return Value::new_err("NotCallable");
+   };
+   bound.extend(arguments);
+   call_function(data, state, function_id, bound)
+}
+
+pub fn call_by_name(data: &Data, state: &mut State, function_name: &str, parameters: &[Expr]) -> Option<Value> {
    data
       .functions
       .iter()
       .enumerate()
       .find(|(_, function)| function.name == function_name)
-      .and_then(|(function_id, _)| call(data, state, function_id, parameters, None))
+      .and_then(|(function_id, _)| call(data, state, function_id, parameters, &[], vec![]))
 }
@@ -1,10 +1,31 @@
-use std::{cell::RefCell, error::Error, fmt::Write, fs, rc::Rc};
+use std::{
+   cell::RefCell,
+   cmp::Ordering,
+   collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+   error::Error,
+   fmt::Write as _,
+   fs,
+   hash::{Hash, Hasher},
+   io::{self, BufRead, SeekFrom, Write},
+   rc::Rc
+};
 
-use crate::{ir::Data, misc::*};
+use num_bigint::{BigInt, BigUint};
+use num_integer::Integer;
+use num_traits::{FromPrimitive, One, Pow, Signed, ToPrimitive};
+
+use crate::{
+   file::FileHandle,
+   ir::{Data, Literal},
+   iter::NativeIterator,
+   misc::*
+};
 
 #[derive(Debug, Clone)]
 pub struct Struct {
    pub prototype: usize,
+   /// Index for Prototype.variants if the prototype is an enum.
+   pub variant: Option<usize>,
    pub values: Vec<Value>
 }
 
@@ -16,12 +37,38 @@ pub enum Value {
    Err(Box<Value>),
    Bool(bool),
    Int(i64),
+   /// Int which does not fit in a i64, never holds a value which does.
+   BigInt(Rc<BigInt>),
    Float(f64),
    Str(Rc<str>),
    List(Rc<RefCell<Vec<Value>>>),
+   /// Immutable sequence of values.
+   Tuple(Rc<[Value]>),
    Struct(Rc<RefCell<Struct>>),
    Function(usize),
-   Method { function_id: usize, instance: Rc<RefCell<Struct>> }
+   Method {
+      function_id: usize,
+      instance: Rc<RefCell<Struct>>
+   },
+   /// Callable with arguments bound to its first parameters by partial().
+   Partial {
+      callable: Rc<Value>,
+      arguments: Rc<[Value]>
+   },
+   Iterator(Rc<RefCell<NativeIterator>>),
+   /// Lazy sequence of ints from start up to but not including stop, step is
+   /// never 0.
+   Range {
+      start: i64,
+      stop: i64,
+      step: i64
+   },
+   File(Rc<RefCell<FileHandle>>),
+   /// Prototype of a struct, or of a variant of a enum if variant is set.
+   Prototype {
+      prototype: usize,
+      variant: Option<usize>
+   }
 }
 
 impl From<bool> for Value {
@@ -42,6 +89,15 @@ impl From<i64> for Value {
    }
 }
 
+impl From<BigInt> for Value {
+   fn from(int: BigInt) -> Self {
+      match int.to_i64() {
+         Some(int) => Value::Int(int),
+         None => Value::BigInt(Rc::new(int))
+      }
+   }
+}
+
 impl From<f64> for Value {
    fn from(float: f64) -> Self {
       Self::Float(float)
@@ -54,6 +110,12 @@ impl From<Struct> for Value {
    }
 }
 
+impl From<Vec<Value>> for Value {
+   fn from(list: Vec<Value>) -> Self {
+      Self::List(Rc::new(list.into()))
+   }
+}
+
 impl From<&str> for Value {
    fn from(str: &str) -> Self {
       Value::Str(str.into())
@@ -66,6 +128,20 @@ impl From<String> for Value {
    }
 }
 
+impl From<&Literal> for Value {
+   fn from(literal: &Literal) -> Self {
+      // FIXME: Cache literals.
+      match literal {
+         Literal::Nil => Value::Nil,
+         Literal::IterEnd => Value::IterEnd,
+         Literal::Bool(bool) => Value::Bool(*bool),
+         Literal::Int(int) => Value::Int(*int),
+         Literal::Float(float) => Value::Float(*float),
+         Literal::Str(str) => Value::Str(str.clone().into())
+      }
+   }
+}
+
 static TYPE_NAME_NIL: &str = "nil";
 static TYPE_NAME_ITEREND: &str = "iterend";
 static TYPE_NAME_ERR: &str = "err";
@@ -74,8 +150,13 @@ static TYPE_NAME_INT: &str = "int";
 static TYPE_NAME_FLOAT: &str = "float";
 static TYPE_NAME_STR: &str = "str";
 static TYPE_NAME_LIST: &str = "list";
+static TYPE_NAME_TUPLE: &str = "tuple";
 //static TYPE_NAME_STRUCT: &str = "struct";
 static TYPE_NAME_FUNCTION: &str = "function";
+static TYPE_NAME_ITERATOR: &str = "iterator";
+static TYPE_NAME_FILE: &str = "file";
+static TYPE_NAME_RANGE: &str = "range";
+static TYPE_NAME_PROTOTYPE: &str = "prototype";
 
 // Cache for the values returned by the type name operator.
 thread_local! {
@@ -87,13 +168,39 @@ thread_local! {
    static TYPE_NAME_FLOAT_VALUE: Value = TYPE_NAME_FLOAT.into();
    static TYPE_NAME_STR_VALUE: Value = TYPE_NAME_STR.into();
    static TYPE_NAME_LIST_VALUE: Value = TYPE_NAME_LIST.into();
+   static TYPE_NAME_TUPLE_VALUE: Value = TYPE_NAME_TUPLE.into();
    //static TYPE_NAME_STRUCT_VALUE: Value = TYPE_NAME_STRUCT.into();
    static TYPE_NAME_FUNCTION_VALUE: Value = TYPE_NAME_FUNCTION.into();
+   static TYPE_NAME_ITERATOR_VALUE: Value = TYPE_NAME_ITERATOR.into();
+   static TYPE_NAME_FILE_VALUE: Value = TYPE_NAME_FILE.into();
+   static TYPE_NAME_RANGE_VALUE: Value = TYPE_NAME_RANGE.into();
+   static TYPE_NAME_PROTOTYPE_VALUE: Value = TYPE_NAME_PROTOTYPE.into();
 }
 
 impl Struct {
    pub fn eq(&self, other: &Struct) -> bool {
-      self.prototype != other.prototype && self.values.iter().zip(other.values.iter()).all(|(left, right)| left.eq(right))
+      self.prototype == other.prototype
+         && self.variant == other.variant
+         && self.values.iter().zip(other.values.iter()).all(|(left, right)| left.eq(right))
+   }
+
+   /// field ident id -> index for values, from the variant if this is a enum.
+   pub fn field_map<'a>(&self, data: &'a Data) -> &'a BTreeMap<usize, usize> {
+      let prototype = &data.prototypes[self.prototype];
+      match self.variant {
+         Some(variant) => &prototype.variants[variant].field_map,
+         None => &prototype.field_map
+      }
+   }
+
+   /// Returns the name of the prototype, followed by the variant if this is a
+   /// enum.
+   pub fn name(&self, data: &Data) -> String {
+      let prototype = &data.prototypes[self.prototype];
+      match self.variant {
+         Some(variant) => format!("{}::{}", prototype.name, prototype.variants[variant].name),
+         None => prototype.name.clone()
+      }
    }
 }
 
@@ -109,6 +216,37 @@ impl Value {
       }
    }
 
+   /// Writes the string escaped and surrounded by quotes. The escapes are the
+   /// ones string literals understand, so the output can be pasted back into
+   /// source code.
+   fn fmt_str(str: &str, into: &mut String) {
+      into.push('"');
+      for char in str.chars() {
+         match char {
+            '"' => into.push_str("\\\""),
+            '\\' => into.push_str("\\\\"),
+            '\n' => into.push_str("\\n"),
+            '\r' => into.push_str("\\r"),
+            '\t' => into.push_str("\\t"),
+            char if char.is_control() => write!(into, "\\u{{{:x}}}", char as u32).unwrap(),
+            char => into.push(char)
+         }
+      }
+      into.push('"');
+   }
+
+   /// Writes the display representation, which is used by print and str.
+   /// Strings are written as-is, all other values use the debug
+   /// representation.
+   pub fn display(&self, data: &Data, into: &mut String) {
+      match self {
+         Value::Str(str) => into.push_str(str),
+         _ => self.fmt(data, into)
+      }
+   }
+
+   /// Writes the debug representation, which is used by repr and for values
+   /// nested inside other values.
    pub fn fmt(&self, data: &Data, into: &mut String) {
       match self {
          Value::Nil => write!(into, "{TYPE_NAME_NIL}").unwrap(),
@@ -121,100 +259,210 @@ impl Value {
             write!(into, ")").unwrap();
          },
          Value::Int(int) => write!(into, "{int}").unwrap(),
+         Value::BigInt(int) => write!(into, "{int}").unwrap(),
          Value::Float(float) => write!(into, "{float}").unwrap(),
-         Value::Str(str) => write!(into, "\"{str}\"").unwrap(),
+         Value::Str(str) => Value::fmt_str(str, into),
          Value::List(list) => {
             write!(into, "[").unwrap();
             Value::fmt_join(data, into, list.borrow().iter(), ", ", |v, data, into| v.fmt(data, into));
             write!(into, "]").unwrap();
          },
+         Value::Tuple(tuple) => {
+            write!(into, "(").unwrap();
+            Value::fmt_join(data, into, tuple.iter(), ", ", |v, data, into| v.fmt(data, into));
+            // A tuple of one value is written as (value,) like its literal.
+            write!(into, "{}", if tuple.len() == 1 { ",)" } else { ")" }).unwrap();
+         },
          Value::Struct(instance) => {
             let instance = instance.borrow();
-            let prototype = &data.prototypes[instance.prototype];
-            write!(into, "{} {{", prototype.name).unwrap();
-            Value::fmt_join(data, into, prototype.field_map.iter(), ", ", |(field_id, index), data, into| {
+            let field_map = instance.field_map(data);
+            write!(into, "{}", instance.name(data)).unwrap();
+            if field_map.is_empty() {
+               // Variants without fields are written like their literal.
+               if instance.variant.is_none() {
+                  write!(into, " {{}}").unwrap();
+               }
+               return;
+            }
+            write!(into, " {{ ").unwrap();
+            Value::fmt_join(data, into, field_map.iter(), ", ", |(field_id, index), data, into| {
                write!(into, "{} = ", data.ident_map[field_id]).unwrap();
                instance.values[*index].fmt(data, into)
             });
-            write!(into, "}}").unwrap();
+            write!(into, " }}").unwrap();
          },
          &Value::Function(function_id) => {
             write!(into, "{}()", data.functions[function_id].name).unwrap();
          },
          &Value::Method { function_id, .. } => {
             write!(into, "{}(bound)", data.functions[function_id].name).unwrap();
+         },
+         Value::Partial { callable, .. } => {
+            let mut callable = callable;
+            while let Value::Partial { callable: inner, .. } = &**callable {
+               callable = inner;
+            }
+            match **callable {
+               Value::Function(function_id) | Value::Method { function_id, .. } =>
+                  write!(into, "{}", data.functions[function_id].name).unwrap(),
+               ref callable => callable.fmt(data, into)
+            }
+            write!(into, "(partial)").unwrap();
+         },
+         Value::Iterator(..) => write!(into, "<{TYPE_NAME_ITERATOR}>").unwrap(),
+         Value::Range { start, stop, step } => write!(into, "{TYPE_NAME_RANGE}({start}, {stop}, {step})").unwrap(),
+         Value::File(file) => {
+            write!(into, "<{TYPE_NAME_FILE} ").unwrap();
+            Value::fmt_str(&file.borrow().path, into);
+            write!(into, ">").unwrap();
+         },
+         &Value::Prototype { prototype, variant } => {
+            let name = Struct { prototype, variant, values: vec![] }.name(data);
+            write!(into, "<{TYPE_NAME_PROTOTYPE} {name}>").unwrap()
          }
       }
    }
 
    /// Must be used to use Value's as conditions.
    pub fn is_truthy(&self) -> bool {
-      match self {
-         Value::Nil | Value::IterEnd | Value::Err(_) | Value::Bool(false) => false,
-         _ => true
-      }
+      !matches!(self, Value::Nil | Value::IterEnd | Value::Err(_) | Value::Bool(false))
    }
 
    pub fn print(self, data: &Data) -> Value {
       let mut s = String::new();
-      self.fmt(data, &mut s);
+      self.display(data, &mut s);
       println!("{s}");
       Value::Nil
    }
 
+   /// Like print, but does not append a newline.
+   pub fn put(self, data: &Data) -> Value {
+      let mut s = String::new();
+      self.display(data, &mut s);
+      print!("{s}");
+      match io::stdout().flush() {
+         Err(err) => Value::from_error(err),
+         Ok(_) => Value::Nil
+      }
+   }
+
+   /// Like print, but prints to stderr.
+   pub fn eprint(self, data: &Data) -> Value {
+      let mut s = String::new();
+      self.display(data, &mut s);
+      eprintln!("{s}");
+      Value::Nil
+   }
+
+   /// Prints the prompt without a newline and reads a line from stdin.
+   pub fn input(self, data: &Data) -> Value {
+      let mut s = String::new();
+      self.display(data, &mut s);
+      print!("{s}");
+      if let Err(err) = io::stdout().flush() {
+         return Value::from_error(err);
+      }
+      Value::read_line()
+   }
+
+   /// Reads a line from stdin without the trailing newline, returns iterend on
+   /// EOF.
+   pub fn read_line() -> Value {
+      let mut line = String::new();
+      match io::stdin().lock().read_line(&mut line) {
+         Err(err) => Value::from_error(err),
+         Ok(0) => Value::IterEnd,
+         Ok(_) => {
+            trim_newline(&mut line);
+            line.into()
+         }
+      }
+   }
+
+   /// Treat bools as the ints 0 and 1.
+   fn bool_as_int(self) -> Value {
+      match self {
+         Value::Bool(bool) => Value::Int(bool as i64),
+         _ => self
+      }
+   }
+
+   /// Returns the value as a BigInt if it is a int.
+   fn as_bigint(&self) -> Option<BigInt> {
+      match self {
+         &Value::Int(int) => Some(int.into()),
+         Value::BigInt(int) => Some((**int).clone()),
+         _ => None
+      }
+   }
+
+   /// Apply a operation on two ints. checked is used if both fit in a i64, and
+   /// big is used if either does not or if checked overflows. Returns None if
+   /// either value is not a int.
+   fn int_operation(
+      &self,
+      right: &Value,
+      checked: impl FnOnce(i64, i64) -> Option<i64>,
+      big: impl FnOnce(BigInt, BigInt) -> Value
+   ) -> Option<Value> {
+      if let (&Value::Int(left), &Value::Int(right)) = (self, right) {
+         if let Some(result) = checked(left, right) {
+            return Some(result.into());
+         }
+      }
+      Some(big(self.as_bigint()?, right.as_bigint()?))
+   }
+
+   /// Apply a operation on two numbers as floats, returns nil if either value
+   /// is not a number.
+   fn float_operation(&self, right: &Value, operation: impl FnOnce(f64, f64) -> f64) -> Value {
+      match (self.as_f64(), right.as_f64()) {
+         (Some(left), Some(right)) => operation(left, right).into(),
+         _ => Value::Nil
+      }
+   }
+
+   /// Returns true if the value is a int or bool equal to 0.
+   fn is_int_zero(&self) -> bool {
+      matches!(self, Value::Bool(false) | Value::Int(0))
+   }
+
    pub fn add(self, right: Value) -> Value {
-      match (self, right) {
-         (Value::Bool(left), Value::Bool(right)) => (left as i64 + right as i64).into(),
-         (Value::Bool(left), Value::Int(right)) => (left as i64 + right).into(),
-         (Value::Bool(left), Value::Float(right)) => (f64::from(left) + right).into(),
-         (Value::Int(left), Value::Bool(right)) => (left + right as i64).into(),
-         (Value::Float(left), Value::Bool(right)) => (left + f64::from(right)).into(),
-         (Value::Int(left), Value::Int(right)) => (left + right).into(),
-         (Value::Int(left), Value::Float(right)) => (left as f64 + right).into(),
-         (Value::Float(left), Value::Int(right)) => (left + right as f64).into(),
-         (Value::Float(left), Value::Float(right)) => (left + right).into(),
+      let (left, right) = (self.bool_as_int(), right.bool_as_int());
+      if let Some(result) = left.int_operation(&right, i64::checked_add, |left, right| (left + right).into()) {
+         return result;
+      }
+      match (left, right) {
          (Value::Str(left), Value::Str(right)) => format!("{left}{right}").into(),
          (Value::List(left), Value::List(right)) =>
             Value::List(RefCell::new(left.borrow().iter().chain(right.borrow().iter()).cloned().collect()).into()),
-         _ => Value::Nil
+         (Value::Tuple(left), Value::Tuple(right)) => Value::Tuple(left.iter().chain(right.iter()).cloned().collect()),
+         (left, right) => left.float_operation(&right, |left, right| left + right)
       }
    }
 
    pub fn sub(self, right: Value) -> Value {
-      match (self, right) {
-         (Value::Bool(left), Value::Bool(right)) => (left as i64 - right as i64).into(),
-         (Value::Bool(left), Value::Int(right)) => (left as i64 - right).into(),
-         (Value::Bool(left), Value::Float(right)) => (f64::from(left) - right).into(),
-         (Value::Int(left), Value::Bool(right)) => (left - right as i64).into(),
-         (Value::Float(left), Value::Bool(right)) => (left - f64::from(right)).into(),
-         (Value::Int(left), Value::Int(right)) => (left - right).into(),
-         (Value::Int(left), Value::Float(right)) => (left as f64 - right).into(),
-         (Value::Float(left), Value::Int(right)) => (left - right as f64).into(),
-         (Value::Float(left), Value::Float(right)) => (left - right).into(),
-         _ => Value::Nil
-      }
+      let (left, right) = (self.bool_as_int(), right.bool_as_int());
+      left
+         .int_operation(&right, i64::checked_sub, |left, right| (left - right).into())
+         .unwrap_or_else(|| left.float_operation(&right, |left, right| left - right))
    }
 
    pub fn minus(self) -> Value {
-      match self {
-         Value::Bool(value) => (-(value as i64)).into(),
-         Value::Int(value) => (-value).into(),
+      match self.bool_as_int() {
+         Value::Int(value) => value.checked_neg().map(Value::Int).unwrap_or_else(|| (-BigInt::from(value)).into()),
+         Value::BigInt(value) => (-&*value).into(),
          Value::Float(value) => (-value).into(),
          _ => Value::Nil
       }
    }
 
    pub fn mul(self, right: Value) -> Value {
-      match (self, right) {
-         (Value::Bool(left), Value::Bool(right)) => (left as i64).wrapping_mul(right as i64).into(),
-         (Value::Bool(left), Value::Int(right)) => right.wrapping_mul(left as i64).into(),
-         (Value::Bool(left), Value::Float(right)) => (f64::from(left) * right).into(),
-         (Value::Int(left), Value::Bool(right)) => left.wrapping_mul(right as i64).into(),
-         (Value::Float(left), Value::Bool(right)) => (left * f64::from(right)).into(),
-         (Value::Int(left), Value::Int(right)) => left.wrapping_mul(right).into(),
-         (Value::Int(left), Value::Float(right)) => (left as f64 * right).into(),
-         (Value::Float(left), Value::Int(right)) => (left * right as f64).into(),
-         (Value::Float(left), Value::Float(right)) => (left * right).into(),
+      let (left, right) = (self.bool_as_int(), right.bool_as_int());
+      if let Some(result) = left.int_operation(&right, i64::checked_mul, |left, right| (left * right).into()) {
+         return result;
+      }
+      match (left, right) {
          (Value::Str(str), Value::Int(factor)) =>
             if 0 <= factor {
                str.repeat(factor as usize).into()
@@ -229,40 +477,72 @@ impl Value {
             } else {
                Value::List(Rc::new(vec![].into()))
             },
-         _ => Value::Nil
+         (Value::Tuple(tuple), Value::Int(factor)) =>
+            Value::Tuple(std::iter::repeat_with(|| tuple.iter()).take(factor.max(0) as usize).flatten().cloned().collect()),
+         (left, right) => left.float_operation(&right, |left, right| left * right)
       }
    }
 
+   /// Division, ints are divided rounding towards 0. Returns a err if right is
+   /// the int 0.
    pub fn div(self, right: Value) -> Value {
-      match (self, right) {
-         (Value::Bool(left), Value::Bool(right)) => (left as i64 / right as i64).into(),
-         (Value::Bool(left), Value::Int(right)) => (left as i64 / right).into(),
-         (Value::Bool(left), Value::Float(right)) => (f64::from(left) / right).into(),
-         (Value::Int(left), Value::Bool(right)) => (left / right as i64).into(),
-         (Value::Float(left), Value::Bool(right)) => (left / f64::from(right)).into(),
-         (Value::Int(left), Value::Int(right)) => (left / right).into(),
-         (Value::Int(left), Value::Float(right)) => (left as f64 / right).into(),
-         (Value::Float(left), Value::Int(right)) => (left / right as f64).into(),
-         (Value::Float(left), Value::Float(right)) => (left / right).into(),
-         (Value::Str(..), Value::Str(..)) => unimplemented!(),
-         _ => Value::Nil
+      let (left, right) = (self.bool_as_int(), right.bool_as_int());
+      if left.as_bigint().is_some() && right.is_int_zero() {
+         return Value::new_err("Division by zero.");
       }
+      left
+         .int_operation(&right, i64::checked_div, |left, right| (left / right).into())
+         .unwrap_or_else(|| left.float_operation(&right, |left, right| left / right))
    }
 
+   /// Division rounding towards negative infinity, returns a err if a number
+   /// is divided by 0.
+   pub fn floordiv(self, right: Value) -> Value {
+      let (left, right) = (self.bool_as_int(), right.bool_as_int());
+      if left.as_f64().is_some() && (right.is_int_zero() || matches!(right, Value::Float(0.))) {
+         return Value::new_err("Division by zero.");
+      }
+      left
+         .int_operation(&right, checked_floor_div, |left, right| left.div_floor(&right).into())
+         .unwrap_or_else(|| left.float_operation(&right, |left, right| (left / right).floor()))
+   }
+
+   /// Raise left to the power of right. A negative int exponent gives a float,
+   /// returns a err if 0 is raised to a negative power.
+   pub fn pow(self, right: Value) -> Value {
+      let (base, exponent) = (self.bool_as_int(), right.bool_as_int());
+      let negative_exponent = exponent.as_f64().is_some_and(|exponent| exponent < 0.);
+      if base.as_f64() == Some(0.) && negative_exponent {
+         return Value::new_err("Division by zero.");
+      }
+      if negative_exponent {
+         return base.float_operation(&exponent, f64::powf);
+      }
+      base
+         .int_operation(&exponent, checked_pow, |base, exponent| match exponent.to_u32() {
+            Some(exponent) => Pow::pow(base, exponent).into(),
+            // Only 0, 1 and -1 can be raised to such a power in memory.
+            None if base.magnitude() <= &BigUint::one() =>
+               if exponent.is_even() {
+                  base.abs().into()
+               } else {
+                  base.into()
+               },
+            None => Value::new_err("Integer overflow.")
+         })
+         .unwrap_or_else(|| base.float_operation(&exponent, f64::powf))
+   }
+
+   /// Remainder with the same sign as right, returns a err if right is the int
+   /// 0.
    pub fn modulo(self, right: Value) -> Value {
-      match (self, right) {
-         (Value::Bool(left), Value::Bool(right)) => (modulo(left.into(), right.into())).into(),
-         (Value::Bool(left), Value::Int(right)) => (modulo(left.into(), right)).into(),
-         (Value::Bool(left), Value::Float(right)) => (fmodulo(left.into(), right)).into(),
-         (Value::Int(left), Value::Bool(right)) => (modulo(left, right.into())).into(),
-         (Value::Float(left), Value::Bool(right)) => (fmodulo(left, right.into())).into(),
-         (Value::Int(left), Value::Int(right)) => (modulo(left, right)).into(),
-         (Value::Int(left), Value::Float(right)) => (fmodulo(left as f64, right)).into(),
-         (Value::Float(left), Value::Int(right)) => (fmodulo(left, right as f64)).into(),
-         (Value::Float(left), Value::Float(right)) => (fmodulo(left, right)).into(),
-         (Value::Str(..), Value::Str(..)) => unimplemented!(),
-         _ => Value::Nil
+      let (left, right) = (self.bool_as_int(), right.bool_as_int());
+      if left.as_bigint().is_some() && right.is_int_zero() {
+         return Value::new_err("Division by zero.");
       }
+      left
+         .int_operation(&right, |left, right| Some(modulo(left, right)), |left, right| left.mod_floor(&right).into())
+         .unwrap_or_else(|| left.float_operation(&right, fmodulo))
    }
 
    pub fn eq(&self, other: &Value) -> bool {
@@ -270,23 +550,114 @@ impl Value {
          (Value::Nil, Value::Nil) => true,
          (Value::IterEnd, Value::IterEnd) => true,
          (Value::Bool(left), Value::Bool(right)) => left == right,
-         (&Value::Bool(left), &Value::Int(right)) => left as i64 == right,
-         (&Value::Bool(left), &Value::Float(right)) => left as i64 as f64 == right,
-         (&Value::Int(left), &Value::Bool(right)) => left == right as i64,
-         (&Value::Float(left), &Value::Bool(right)) => left == right as i64 as f64,
          (Value::Int(left), Value::Int(right)) => left == right,
-         (Value::Float(left), Value::Float(right)) => left == right,
-         (&Value::Int(left), &Value::Float(right)) => left == right as i64,
-         (&Value::Float(left), &Value::Int(right)) => left as i64 == right,
+         (
+            Value::Bool(_) | Value::Int(_) | Value::BigInt(_) | Value::Float(_),
+            Value::Bool(_) | Value::Int(_) | Value::BigInt(_) | Value::Float(_)
+         ) => self.compare(other) == Some(Ordering::Equal),
          (Value::Str(left), Value::Str(right)) => left == right,
-         (Value::Err(left), Value::Err(right)) => left.eq(&right),
-         (Value::List(left), Value::List(right)) => left.borrow().iter().zip(right.borrow().iter()).all(|(l, r)| l.eq(r)),
+         (Value::Err(left), Value::Err(right)) => left.eq(right),
+         (Value::List(left), Value::List(right)) => Value::eq_slices(&left.borrow(), &right.borrow()),
+         (Value::Tuple(left), Value::Tuple(right)) => Value::eq_slices(left, right),
          (Value::Struct(left), Value::Struct(right)) => left.borrow().eq(&right.borrow()),
          (Value::Function(left), Value::Function(right)) => left == right,
+         (Value::Iterator(left), Value::Iterator(right)) => Rc::ptr_eq(left, right),
+         (Value::File(left), Value::File(right)) => Rc::ptr_eq(left, right),
+         (
+            Value::Prototype { prototype: left_prototype, variant: left_variant },
+            Value::Prototype { prototype: right_prototype, variant: right_variant }
+         ) => left_prototype == right_prototype && left_variant == right_variant,
+         (
+            &Value::Range { start: left_start, stop: left_stop, step: left_step },
+            &Value::Range { start: right_start, stop: right_stop, step: right_step }
+         ) => {
+            // Ranges are equal if they contain the same ints.
+            let len = range_len(left_start, left_stop, left_step);
+            len == range_len(right_start, right_stop, right_step)
+               && (len == 0 || (left_start == right_start && (len == 1 || left_step == right_step)))
+         },
          _ => false
       }
    }
 
+   fn eq_slices(left: &[Value], right: &[Value]) -> bool {
+      left.len() == right.len() && left.iter().zip(right.iter()).all(|(left, right)| left.eq(right))
+   }
+
+   /// Feed the value into hasher so that equal values hash equally. Returns
+   /// false if the value is mutable and so cannot be hashed.
+   pub fn hash(&self, hasher: &mut impl Hasher) -> bool {
+      // Tag each kind of value so that different kinds rarely collide.
+      match self {
+         Value::Nil => 0u8.hash(hasher),
+         Value::IterEnd => 1u8.hash(hasher),
+         Value::Err(err) => {
+            2u8.hash(hasher);
+            return err.hash(hasher);
+         },
+         Value::Bool(_) | Value::Int(_) | Value::BigInt(_) | Value::Float(_) => {
+            // Numbers which are equal hash the same regardless of type, as
+            // integral floats hash like the int they are equal to.
+            3u8.hash(hasher);
+            match self.clone().bool_as_int() {
+               Value::Int(int) => int.hash(hasher),
+               Value::BigInt(int) => int.hash(hasher),
+               Value::Float(float) if float.fract() == 0. => match Value::Float(float).int() {
+                  Value::Int(int) => int.hash(hasher),
+                  Value::BigInt(int) => int.hash(hasher),
+                  _ => float.to_bits().hash(hasher)
+               },
+               Value::Float(float) => float.to_bits().hash(hasher),
+               _ => unreachable!()
+            }
+         },
+         Value::Str(str) => {
+            4u8.hash(hasher);
+            str.hash(hasher);
+         },
+         Value::Tuple(tuple) => {
+            5u8.hash(hasher);
+            tuple.len().hash(hasher);
+            return tuple.iter().all(|value| value.hash(hasher));
+         },
+         Value::Function(function_id) => {
+            6u8.hash(hasher);
+            function_id.hash(hasher);
+         },
+         &Value::Range { start, stop, step } => {
+            // Ranges are equal if they contain the same ints.
+            7u8.hash(hasher);
+            let len = range_len(start, stop, step);
+            len.hash(hasher);
+            if 0 < len {
+               start.hash(hasher);
+            }
+            if 1 < len {
+               step.hash(hasher);
+            }
+         },
+         Value::Prototype { prototype, variant } => {
+            8u8.hash(hasher);
+            prototype.hash(hasher);
+            variant.hash(hasher);
+         },
+         Value::Iterator(iterator) => Rc::as_ptr(iterator).hash(hasher),
+         Value::File(file) => Rc::as_ptr(file).hash(hasher),
+         Value::List(..) | Value::Struct(..) | Value::Method { .. } | Value::Partial { .. } => return false
+      }
+      true
+   }
+
+   /// Returns the hash of a value as a int, or a err if it cannot be hashed.
+   pub fn hash_value(self) -> Value {
+      let mut hasher = DefaultHasher::new();
+      if self.hash(&mut hasher) {
+         (hasher.finish() as i64).into()
+      } else {
+         Value::new_err("Unhashable")
+      }
+   }
+
    /// Returns true if both Values are the same memory. Will return false for
    /// equal but unique values.
    pub fn is(&self, other: &Value) -> bool {
@@ -296,119 +667,179 @@ impl Value {
          (Value::Err(left), Value::Err(right)) => left.is(right),
          (Value::Str(left), Value::Str(right)) => Rc::ptr_eq(left, right),
          (Value::List(left), Value::List(right)) => Rc::ptr_eq(left, right),
+         (Value::Tuple(left), Value::Tuple(right)) => Rc::ptr_eq(left, right),
          (Value::Struct(left), Value::Struct(right)) => Rc::ptr_eq(left, right),
          (Value::Function(left), Value::Function(right)) => left == right, // 'is' and '==' on functions are the same thing.
+         (Value::Iterator(left), Value::Iterator(right)) => Rc::ptr_eq(left, right),
+         (Value::File(left), Value::File(right)) => Rc::ptr_eq(left, right),
+         (Value::Prototype { .. }, Value::Prototype { .. }) => self.eq(other),
          _ => false
       }
    }
 
+   /// Returns the value as a float if it is a number.
+   fn as_f64(&self) -> Option<f64> {
+      match *self {
+         Value::Bool(bool) => Some(f64::from(bool)),
+         Value::Int(int) => Some(int as f64),
+         Value::BigInt(ref int) => int.to_f64(),
+         Value::Float(float) => Some(float),
+         _ => None
+      }
+   }
+
+   /// Returns the ordering of two values, or None if they cannot be compared.
+   /// Numbers are compared by value, strs and lists are compared
+   /// lexicographically.
+   pub fn compare(&self, other: &Value) -> Option<Ordering> {
+      match (self, other) {
+         (Value::Bool(left), Value::Bool(right)) => Some(left.cmp(right)),
+         (Value::Int(left), Value::Int(right)) => Some(left.cmp(right)),
+         (&Value::Bool(left), Value::Int(right)) => Some((left as i64).cmp(right)),
+         (Value::Int(left), &Value::Bool(right)) => Some(left.cmp(&(right as i64))),
+         (Value::BigInt(_), Value::Bool(_) | Value::Int(_) | Value::BigInt(_))
+         | (Value::Bool(_) | Value::Int(_), Value::BigInt(_)) =>
+            Some(self.clone().bool_as_int().as_bigint()?.cmp(&other.clone().bool_as_int().as_bigint()?)),
+         (Value::Bool(_) | Value::Int(_) | Value::BigInt(_), &Value::Float(right)) =>
+            Value::compare_int_float(&self.clone().bool_as_int().as_bigint()?, right),
+         (&Value::Float(left), Value::Bool(_) | Value::Int(_) | Value::BigInt(_)) =>
+            Some(Value::compare_int_float(&other.clone().bool_as_int().as_bigint()?, left)?.reverse()),
+         (Value::Str(left), Value::Str(right)) => Some(left.cmp(right)),
+         (Value::List(left), Value::List(right)) => Value::compare_slices(&left.borrow(), &right.borrow()),
+         (Value::Tuple(left), Value::Tuple(right)) => Value::compare_slices(left, right),
+         _ => self.as_f64()?.partial_cmp(&other.as_f64()?)
+      }
+   }
+
+   /// Compare a int with a float exactly, instead of rounding the int to the
+   /// nearest float.
+   fn compare_int_float(int: &BigInt, float: f64) -> Option<Ordering> {
+      if float.is_nan() {
+         return None;
+      }
+      if float.is_infinite() {
+         return Some(if 0. < float { Ordering::Less } else { Ordering::Greater });
+      }
+      let floor = float.floor();
+      Some(int.cmp(&BigInt::from_f64(floor)?).then(if float == floor { Ordering::Equal } else { Ordering::Less }))
+   }
+
+   fn compare_slices(left: &[Value], right: &[Value]) -> Option<Ordering> {
+      for (left, right) in left.iter().zip(right.iter()) {
+         match left.compare(right)? {
+            Ordering::Equal => {},
+            ordering => return Some(ordering)
+         }
+      }
+      Some(left.len().cmp(&right.len()))
+   }
+
    pub fn lt(self, other: Value) -> Value {
-      match self {
-         Value::Bool(left) => match other {
-            Value::Bool(right) => (left < right).into(),
-            Value::Int(right) => ((left as i64) < right).into(),
-            Value::Float(right) => ((f64::from(left)) < right).into(),
-            _ => Value::Nil
-         },
-         Value::Int(left) => match other {
-            Value::Bool(right) => (left < right as i64).into(),
-            Value::Int(right) => (left < right).into(),
-            Value::Float(right) => ((left as f64) < right).into(),
-            _ => Value::Nil
-         },
-         Value::Float(left) => match other {
-            Value::Bool(right) => (left < right as i64 as f64).into(),
-            Value::Int(right) => (left < right as f64).into(),
-            Value::Float(right) => (left < right).into(),
-            _ => Value::Nil
-         },
-         _ => Value::Nil
+      match self.compare(&other) {
+         Some(ordering) => (ordering == Ordering::Less).into(),
+         // NaN is not less than any number.
+         None if self.as_f64().is_some() && other.as_f64().is_some() => false.into(),
+         None => Value::Nil
       }
    }
 
    pub fn leq(self, other: Value) -> Value {
-      match self {
-         Value::Bool(left) => match other {
-            Value::Bool(right) => (left <= right).into(),
-            Value::Int(right) => ((left as i64) <= right).into(),
-            Value::Float(right) => ((f64::from(left)) <= right).into(),
-            _ => Value::Nil
-         },
-         Value::Int(left) => match other {
-            Value::Bool(right) => (left <= right as i64).into(),
-            Value::Int(right) => (left <= right).into(),
-            Value::Float(right) => ((left as f64) <= right).into(),
-            _ => Value::Nil
-         },
-         Value::Float(left) => match other {
-            Value::Bool(right) => (left <= f64::from(right)).into(),
-            Value::Int(right) => (left <= right as f64).into(),
-            Value::Float(right) => (left <= right).into(),
-            _ => Value::Nil
-         },
-         _ => Value::Nil
+      match self.compare(&other) {
+         Some(ordering) => (ordering != Ordering::Greater).into(),
+         None if self.as_f64().is_some() && other.as_f64().is_some() => false.into(),
+         None => Value::Nil
       }
    }
 
    pub fn bitnot(self) -> Value {
       match self {
          Value::Int(int) => (!int).into(),
+         Value::BigInt(int) => (!&*int).into(),
          _ => Value::Nil
       }
    }
 
    pub fn bitand(self, other: Value) -> Value {
-      match (self, other) {
-         (Value::Int(left), Value::Int(right)) => (left & right).into(),
-         _ => Value::Nil
-      }
+      self.int_operation(&other, |left, right| Some(left & right), |left, right| (left & right).into()).unwrap_or(Value::Nil)
    }
 
    pub fn bitor(self, other: Value) -> Value {
-      match (self, other) {
-         (Value::Int(left), Value::Int(right)) => (left | right).into(),
-         _ => Value::Nil
-      }
+      self.int_operation(&other, |left, right| Some(left | right), |left, right| (left | right).into()).unwrap_or(Value::Nil)
    }
 
    pub fn bitxor(self, other: Value) -> Value {
-      match (self, other) {
-         (Value::Int(left), Value::Int(right)) => (left ^ right).into(),
-         _ => Value::Nil
-      }
+      self.int_operation(&other, |left, right| Some(left ^ right), |left, right| (left ^ right).into()).unwrap_or(Value::Nil)
    }
 
+   /// Returns a err if the shift is negative.
    pub fn leftshift(self, other: Value) -> Value {
-      match (self, other) {
-         (Value::Int(left), Value::Int(right)) => (left << right).into(),
-         _ => Value::Nil
-      }
+      self
+         .int_operation(
+            &other,
+            |left, right| (0..64).contains(&right).then(|| left << right).filter(|shifted| shifted >> right == left),
+            |left, right| match right.to_usize() {
+               _ if right.is_negative() => Value::new_err("Negative shift count."),
+               Some(right) => (left << right).into(),
+               None => Value::new_err("Integer overflow.")
+            }
+         )
+         .unwrap_or(Value::Nil)
    }
 
+   /// Returns a err if the shift is negative.
    pub fn rightshift(self, other: Value) -> Value {
-      match (self, other) {
-         (Value::Int(left), Value::Int(right)) => (left >> right).into(),
-         _ => Value::Nil
-      }
+      self
+         .int_operation(
+            &other,
+            |left, right| (0 <= right).then(|| left >> right.min(63)),
+            |left, right| match right.to_usize() {
+               _ if right.is_negative() => Value::new_err("Negative shift count."),
+               Some(right) => (left >> right).into(),
+               None => Value::Int(if left.is_negative() { -1 } else { 0 })
+            }
+         )
+         .unwrap_or(Value::Nil)
    }
 
    pub fn getitem(self, other: Value) -> Value {
       match (self, other) {
          (Value::Str(str), Value::Int(index)) =>
-            (if index < 0 { str.chars().nth_back((1 - index) as usize) } else { str.chars().nth(index as usize) })
+            (if index < 0 { str.chars().nth_back((-(index + 1)) as usize) } else { str.chars().nth(index as usize) })
                .map(|v| v.to_string().into())
                .unwrap_or(Value::Nil),
-         (Value::List(list), Value::Int(mut index)) => {
+         (Value::List(list), Value::Int(index)) => {
             let list = list.borrow();
+            resolve_index(index, list.len()).map(|index| list[index].clone()).unwrap_or(Value::Nil)
+         },
+         (Value::Tuple(tuple), Value::Int(index)) =>
+            resolve_index(index, tuple.len()).map(|index| tuple[index].clone()).unwrap_or(Value::Nil),
+         (Value::Range { start, stop, step }, Value::Int(mut index)) => {
+            let len = range_len(start, stop, step);
             if index < 0 {
-               index = list.len() as i64 - index;
+               index += len;
+            }
+            if 0 <= index && index < len {
+               // Computed as i128 because index * step can overflow even though
+               // the element itself always fits in the range.
+               let item = start as i128 + index as i128 * step as i128;
+               i64::try_from(item).map_or(Value::Nil, Value::Int)
+            } else {
+               Value::Nil
             }
-            list.get(index as usize).cloned().unwrap_or(Value::Nil)
          },
          _ => Value::Nil
       }
    }
 
+   /// Creates a range, returns a err if step is 0.
+   pub fn range(start: Value, stop: Value, step: Value) -> Value {
+      match (start, stop, step) {
+         (Value::Int(_), Value::Int(_), Value::Int(0)) => Value::new_err("Range step cannot be 0."),
+         (Value::Int(start), Value::Int(stop), Value::Int(step)) => Value::Range { start, stop, step },
+         _ => Value::new_err("TypeError")
+      }
+   }
+
    /// Returns the name of the Value's type as a str.
    pub fn typename(self, data: &Data) -> Value {
       match self {
@@ -416,17 +847,34 @@ impl Value {
          Value::IterEnd => TYPE_NAME_ITEREND_VALUE.with(|v| v.clone()),
          Value::Err(..) => TYPE_NAME_ERR_VALUE.with(|v| v.clone()),
          Value::Bool(..) => TYPE_NAME_BOOL_VALUE.with(|v| v.clone()),
-         Value::Int(..) => TYPE_NAME_INT_VALUE.with(|v| v.clone()),
+         Value::Int(..) | Value::BigInt(..) => TYPE_NAME_INT_VALUE.with(|v| v.clone()),
          Value::Float(..) => TYPE_NAME_FLOAT_VALUE.with(|v| v.clone()),
          Value::Str(..) => TYPE_NAME_STR_VALUE.with(|v| v.clone()),
          Value::List(..) => TYPE_NAME_LIST_VALUE.with(|v| v.clone()),
+         Value::Tuple(..) => TYPE_NAME_TUPLE_VALUE.with(|v| v.clone()),
          Value::Struct(instance) => {
             let instance = instance.borrow();
             data.prototypes[instance.prototype].name.clone().into()
          }, // TODO: Cache this
          Value::Function(..) => TYPE_NAME_FUNCTION_VALUE.with(|v| v.clone()),
-         Value::Method { .. } => TYPE_NAME_FUNCTION_VALUE.with(|v| v.clone())
+         Value::Method { .. } => TYPE_NAME_FUNCTION_VALUE.with(|v| v.clone()),
+         Value::Partial { .. } => TYPE_NAME_FUNCTION_VALUE.with(|v| v.clone()),
+         Value::Iterator(..) => TYPE_NAME_ITERATOR_VALUE.with(|v| v.clone()),
+         Value::File(..) => TYPE_NAME_FILE_VALUE.with(|v| v.clone()),
+         Value::Range { .. } => TYPE_NAME_RANGE_VALUE.with(|v| v.clone()),
+         Value::Prototype { .. } => TYPE_NAME_PROTOTYPE_VALUE.with(|v| v.clone())
+      }
+   }
+
+   /// Returns the name of the variant of a enum instance, or nil.
+   pub fn variant(self, data: &Data) -> Value {
+      if let Value::Struct(instance) = self {
+         let instance = instance.borrow();
+         if let Some(variant) = instance.variant {
+            return data.prototypes[instance.prototype].variants[variant].name.clone().into();
+         }
       }
+      Value::Nil
    }
 
    pub fn not(self) -> Value {
@@ -447,9 +895,13 @@ impl Value {
    pub fn int(self) -> Value {
       match self {
          Value::Bool(bool) => (bool as i64).into(),
-         Value::Int(..) => self,
-         Value::Float(float) => (float as i64).into(),
-         Value::Str(str) => str.parse::<i64>().ok().and_then(|v| Some(v.into())).unwrap_or(Value::Nil),
+         Value::Int(..) | Value::BigInt(..) => self,
+         Value::Float(float) if (i64::MIN as f64..i64::MAX as f64).contains(&float) => (float as i64).into(),
+         Value::Float(float) => BigInt::from_f64(float).map(Value::from).unwrap_or(Value::Nil),
+         Value::Str(str) => match str.parse::<i64>() {
+            Ok(int) => int.into(),
+            Err(_) => str.parse::<BigInt>().ok().map(Value::from).unwrap_or(Value::Nil)
+         },
          _ => Value::Nil
       }
    }
@@ -458,13 +910,21 @@ impl Value {
       match self {
          Value::Bool(bool) => f64::from(bool).into(),
          Value::Int(int) => (int as f64).into(),
+         Value::BigInt(int) => int.to_f64().map(Value::from).unwrap_or(Value::Nil),
          Value::Float(..) => self,
-         Value::Str(str) => str.parse::<f64>().ok().and_then(|v| Some(v.into())).unwrap_or(Value::Nil),
+         Value::Str(str) => str.parse::<f64>().ok().map(|v| v.into()).unwrap_or(Value::Nil),
          _ => Value::Nil
       }
    }
 
    pub fn str(self, data: &Data) -> Value {
+      match self {
+         Value::Str(..) => self,
+         _ => self.repr(data)
+      }
+   }
+
+   pub fn repr(self, data: &Data) -> Value {
       let mut s = String::new();
       self.fmt(data, &mut s);
       s.into()
@@ -477,6 +937,19 @@ impl Value {
             _ => Value::Nil
          },
          Value::List(list) => list.borrow().iter().position(|v| v.eq(&other)).map(usize::into).unwrap_or(Value::Nil),
+         Value::Tuple(tuple) => tuple.iter().position(|v| v.eq(&other)).map(usize::into).unwrap_or(Value::Nil),
+         Value::Range { start, stop, step } => match other {
+            Value::Int(int) => {
+               let offset = int as i128 - start as i128;
+               let index = offset / step as i128;
+               if offset % step as i128 == 0 && 0 <= index && index < range_len(start, stop, step) as i128 {
+                  (index as i64).into()
+               } else {
+                  Value::Nil
+               }
+            },
+            _ => Value::Nil
+         },
          _ => Value::Nil
       }
    }
@@ -485,53 +958,166 @@ impl Value {
       match self {
          Value::Str(str) => str.chars().count().into(),
          Value::List(list) => list.borrow().len().into(),
+         Value::Tuple(tuple) => tuple.len().into(),
+         Value::Range { start, stop, step } => range_len(start, stop, step).into(),
          _ => Value::Nil
       }
    }
 
    pub fn remove(self, other: Value) -> Value {
-      match other {
-         Value::Int(mut index) => match self {
-            Value::List(list) => {
-               let mut list = list.borrow_mut();
-               if index < 0 {
-                  index = list.len() as i64 - index;
-               }
-               if index < list.len() as i64 {
-                  list.remove(index as usize)
-               } else {
-                  Value::Nil
-               }
-            },
-            _ => Value::Nil
+      match (self, other) {
+         (Value::List(list), Value::Int(index)) => {
+            let mut list = list.borrow_mut();
+            match resolve_index(index, list.len()) {
+               Some(index) => list.remove(index),
+               None => Value::Nil
+            }
          },
          _ => Value::Nil
       }
    }
 
-   pub fn push(self, other: Value) -> Value {
+   /// Remove and return the last element of the list.
+   pub fn pop(self) -> Value {
       match self {
-         Value::List(list) => list.borrow_mut().push(other),
-         _ => {}
+         Value::List(list) => list.borrow_mut().pop().unwrap_or(Value::Nil),
+         _ => Value::Nil
+      }
+   }
+
+   /// Insert element before index, the index is clamped to the list.
+   pub fn insert(self, index: Value, element: Value) -> Value {
+      if let (Value::List(list), Value::Int(index)) = (self, index) {
+         let mut list = list.borrow_mut();
+         let index = clamp_index(index, list.len());
+         list.insert(index, element);
       }
       Value::Nil
    }
 
-   pub fn setitem(self, key: Value, item: Value) -> Value {
+   pub fn clear(self) -> Value {
+      if let Value::List(list) = self {
+         list.borrow_mut().clear();
+      }
+      Value::Nil
+   }
+
+   pub fn reverse(self) -> Value {
+      if let Value::List(list) = self {
+         list.borrow_mut().reverse();
+      }
+      Value::Nil
+   }
+
+   /// Returns true if other is a substring of a str, an element of a list or a
+   /// int in a range.
+   pub fn contains(self, other: Value) -> Value {
+      match (self, other) {
+         (Value::Str(str), Value::Str(substr)) => str.contains(&*substr).into(),
+         (Value::List(list), other) => list.borrow().iter().any(|v| v.eq(&other)).into(),
+         (Value::Tuple(tuple), other) => tuple.iter().any(|v| v.eq(&other)).into(),
+         (range @ Value::Range { .. }, other @ Value::Int(_)) => (!matches!(range.index(other), Value::Nil)).into(),
+         _ => Value::Nil
+      }
+   }
+
+   /// Returns the number of elements equal to other.
+   pub fn count(self, other: Value) -> Value {
       match self {
-         Value::List(list) => match key {
-            Value::Int(mut index) => {
-               let mut list = list.borrow_mut();
-               if index < 0 {
-                  index = list.len() as i64 - index;
-               }
-               if index < list.len() as i64 {
-                  list[index as usize] = item;
-               }
-            },
-            _ => {}
+         Value::List(list) => list.borrow().iter().filter(|v| v.eq(&other)).count().into(),
+         Value::Tuple(tuple) => tuple.iter().filter(|v| v.eq(&other)).count().into(),
+         _ => Value::Nil
+      }
+   }
+
+   /// Returns a new str, list or tuple of the elements from start up to stop by
+   /// step, like Python. nil bounds default to the start or end of the
+   /// sequence and a nil step is 1. Returns a err if step is 0.
+   pub fn slice(self, start: Value, stop: Value, step: Value) -> Value {
+      let bound = |value| match value {
+         Value::Nil => Ok(None),
+         Value::Int(index) => Ok(Some(index)),
+         _ => Err(())
+      };
+      let (Ok(start), Ok(stop)) = (bound(start), bound(stop)) else { return Value::Nil };
+      let step = match step {
+         Value::Nil => 1,
+         Value::Int(0) => return Value::new_err("Slice step cannot be 0."),
+         Value::Int(step) => step,
+         _ => return Value::Nil
+      };
+      match self {
+         Value::Str(str) => {
+            let chars: Vec<char> = str.chars().collect();
+            slice_indices(chars.len(), start, stop, step).into_iter().map(|index| chars[index]).collect::<String>().into()
+         },
+         Value::List(list) => {
+            let list = list.borrow();
+            slice_indices(list.len(), start, stop, step).into_iter().map(|index| list[index].clone()).collect::<Vec<_>>().into()
          },
-         _ => {}
+         Value::Tuple(tuple) =>
+            Value::Tuple(slice_indices(tuple.len(), start, stop, step).into_iter().map(|index| tuple[index].clone()).collect()),
+         _ => Value::Nil
+      }
+   }
+
+   /// Returns a shallow copy of a list or struct, other values are returned
+   /// as-is.
+   pub fn copy(self) -> Value {
+      match self {
+         Value::List(list) => list.borrow().clone().into(),
+         Value::Struct(instance) => instance.borrow().clone().into(),
+         _ => self
+      }
+   }
+
+   /// Returns a copy of a list, tuple or struct which also copies the lists,
+   /// tuples and structs inside it. Values which are referenced more than once,
+   /// including cycles, are copied once.
+   pub fn deep_copy(self) -> Value {
+      self.deep_copy_with(&mut HashMap::new())
+   }
+
+   fn deep_copy_with(self, copies: &mut HashMap<usize, Value>) -> Value {
+      match &self {
+         Value::List(list) => {
+            if let Some(copy) = copies.get(&(Rc::as_ptr(list) as usize)) {
+               return copy.clone();
+            }
+            let copy = Rc::new(RefCell::new(vec![]));
+            copies.insert(Rc::as_ptr(list) as usize, Value::List(copy.clone()));
+            let values = list.borrow().clone();
+            *copy.borrow_mut() = values.into_iter().map(|v| v.deep_copy_with(copies)).collect();
+            Value::List(copy)
+         },
+         Value::Struct(instance) => {
+            if let Some(copy) = copies.get(&(Rc::as_ptr(instance) as usize)) {
+               return copy.clone();
+            }
+            let Struct { prototype, variant, values } = instance.borrow().clone();
+            let copy = Rc::new(RefCell::new(Struct { prototype, variant, values: vec![] }));
+            copies.insert(Rc::as_ptr(instance) as usize, Value::Struct(copy.clone()));
+            copy.borrow_mut().values = values.into_iter().map(|v| v.deep_copy_with(copies)).collect();
+            Value::Struct(copy)
+         },
+         Value::Tuple(tuple) => Value::Tuple(tuple.iter().map(|v| v.clone().deep_copy_with(copies)).collect()),
+         _ => self
+      }
+   }
+
+   pub fn push(self, other: Value) -> Value {
+      if let Value::List(list) = self {
+         list.borrow_mut().push(other)
+      }
+      Value::Nil
+   }
+
+   pub fn setitem(self, key: Value, item: Value) -> Value {
+      if let (Value::List(list), Value::Int(index)) = (self, key) {
+         let mut list = list.borrow_mut();
+         if let Some(index) = resolve_index(index, list.len()) {
+            list[index] = item;
+         }
       }
       Value::Nil
    }
@@ -540,15 +1126,22 @@ impl Value {
       match self {
          Value::Struct(instance_cell) => {
             let instance = instance_cell.borrow();
-            let prototype = &data.prototypes[instance.prototype];
-            if let Some(&id) = prototype.field_map.get(&field_id) {
+            if let Some(&id) = instance.field_map(data).get(&field_id) {
                instance.values[id].clone()
-            } else if let Some(&id) = prototype.method_map.get(&field_id) {
+            } else if let Some(id) = data.resolve_method(instance.prototype, field_id) {
                Value::Method { function_id: id, instance: instance_cell.clone() }
             } else {
                Value::new_err("FieldDoesNotExist")
             }
          },
+         // Methods of a prototype are not bound, so methods without a self
+         // parameter can be called without a instance.
+         &Value::Prototype { prototype, .. } => match data.resolve_method(prototype, field_id) {
+            Some(function_id) => Value::Function(function_id),
+            None => Value::new_err("FieldDoesNotExist")
+         },
+         // Calling a native iterator advances it, so it is its own next method.
+         Value::Iterator(..) if field_id == data.reserved_idents.next => self.clone(),
          _ => Value::Nil
       }
    }
@@ -560,7 +1153,7 @@ impl Value {
       match self {
          Value::List(list) => {
             let mut s = String::new();
-            Value::fmt_join(data, &mut s, list.borrow().iter(), &sep, Value::fmt);
+            Value::fmt_join(data, &mut s, list.borrow().iter(), &sep, Value::display);
             s.into()
          },
 
@@ -576,26 +1169,87 @@ impl Value {
       Value::Err(Box::new(string.into()))
    }
 
+   pub fn from_io<T: Into<Value>>(result: io::Result<T>) -> Value {
+      match result {
+         Err(err) => Value::from_error(err),
+         Ok(value) => value.into()
+      }
+   }
+
    pub fn read(self) -> Value {
       match self {
-         Value::Str(str) => match fs::read_to_string(&*str) {
+         Value::Str(str) => Value::from_io(fs::read_to_string(&*str)),
+         Value::File(file) => Value::from_io(file.borrow_mut().read_to_end()),
+         _ => Value::new_err("TypeError")
+      }
+   }
+
+   /// Read atmost count characters from a file handle.
+   pub fn read_chars(self, count: Value) -> Value {
+      match (self, count) {
+         (Value::File(file), Value::Int(count)) if 0 <= count => Value::from_io(file.borrow_mut().read_chars(count as usize)),
+         _ => Value::new_err("TypeError")
+      }
+   }
+
+   /// Read a line from a file handle, returns iterend on EOF.
+   pub fn read_file_line(self) -> Value {
+      match self {
+         Value::File(file) => match file.borrow_mut().read_line() {
             Err(err) => Value::from_error(err),
-            Ok(str) => str.into()
+            Ok(None) => Value::IterEnd,
+            Ok(Some(line)) => line.into()
          },
          _ => Value::new_err("TypeError")
       }
    }
 
    pub fn write(self, other: Value) -> Value {
-      match self {
-         Value::Str(path) => match other {
-            Value::Str(str) => match fs::write(&*path, &*str) {
-               Err(err) => Value::from_error(err),
-               Ok(_) => true.into()
-            },
-            _ => Value::new_err("TypeError")
+      match (self, other) {
+         (Value::Str(path), Value::Str(str)) => Value::from_io(fs::write(&*path, &*str).map(|_| true)),
+         (Value::File(file), Value::Str(str)) => Value::from_io(file.borrow_mut().write(&str).map(|_| true)),
+         _ => Value::new_err("TypeError")
+      }
+   }
+
+   pub fn open(self, mode: Value) -> Value {
+      match (self, mode) {
+         (Value::Str(path), Value::Str(mode)) => match FileHandle::open(&path, &mode) {
+            Err(err) => Value::from_error(err),
+            Ok(file) => Value::File(Rc::new(RefCell::new(file)))
          },
          _ => Value::new_err("TypeError")
       }
    }
+
+   /// Seek a file handle to offset relative to whence, which is one of "start",
+   /// "current" or "end". Returns the new position from the start of the
+   /// file.
+   pub fn seek(self, offset: Value, whence: Value) -> Value {
+      let (Value::File(file), Value::Int(offset), Value::Str(whence)) = (self, offset, whence) else {
+         return Value::new_err("TypeError");
+      };
+      let position = match &*whence {
+         "start" if 0 <= offset => SeekFrom::Start(offset as u64),
+         "current" => SeekFrom::Current(offset),
+         "end" => SeekFrom::End(offset),
+         _ => return Value::new_err("InvalidSeek")
+      };
+      let position = file.borrow_mut().seek(position);
+      Value::from_io(position.map(|position| position as usize))
+   }
+
+   pub fn flush(self) -> Value {
+      match self {
+         Value::File(file) => Value::from_io(file.borrow_mut().flush().map(|_| true)),
+         _ => Value::new_err("TypeError")
+      }
+   }
+
+   pub fn close(self) -> Value {
+      match self {
+         Value::File(file) => Value::from_io(file.borrow_mut().close().map(|_| true)),
+         _ => Value::new_err("TypeError")
+      }
+   }
 }